@@ -1,17 +1,56 @@
-use aoc2021::input::load_input;
+use aoc2021::input::{input_lines, load_input};
 use anyhow::Result;
+use std::collections::VecDeque;
 
 fn main() -> Result<()> {
-    let data : Vec<i64> = load_input(1)?;
-    let depth_changes_part1 = find_depth_increases(&data, 1);
-    println!("depth changes part1: {}", depth_changes_part1);
+    let mut json = false;
+    let mut handling = AnomalyHandling::Keep;
+    let mut window_sizes = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--keep-anomalies" => handling = AnomalyHandling::Keep,
+            "--exclude-anomalies" => handling = AnomalyHandling::Exclude,
+            "--interpolate-anomalies" => handling = AnomalyHandling::Interpolate,
+            _ => window_sizes.push(arg.parse::<usize>()?),
+        }
+    }
+    if window_sizes.is_empty() {
+        window_sizes = vec![1, 3];
+    }
+
+    // the report and anomaly detection need every reading at once
+    let measurements : Vec<i64> = load_input(1)?;
+    let detector = AnomalyDetector::default();
+    let data = detector.clean(&measurements, handling);
 
-    let depth_changes_part2 = find_depth_increases(&data, 3);
+    // readings that are kept as they are stream straight from the input
+    let (depth_changes_part1, depth_changes_part2) = match handling {
+        AnomalyHandling::Keep => (count_depth_increases_in(input_lines(1)?, 1)?, count_depth_increases_in(input_lines(1)?, 3)?),
+        _ => (find_depth_increases(&data, 1), find_depth_increases(&data, 3)),
+    };
+    println!("depth changes part1: {}", depth_changes_part1);
     println!("depth changes part2: {}", depth_changes_part2);
+
+    let report = DepthReport::new(&data, &window_sizes);
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report);
+
+        for kind in AggregatorKind::all() {
+            let increases = window_sizes.iter()
+                .map(|window_size| count_depth_increases_with(data.iter().copied(), *window_size, kind.aggregator()).to_string())
+                .collect::<Vec<String>>();
+            println!("{:>6}: {}", kind, increases.join(" "));
+        }
+
+        println!("anomalies: {} ({:?})", detector.detect(&measurements).len(), handling);
+    }
     Ok(())
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum DepthChange {
     NotApplicable,
     NoChange,
@@ -19,63 +58,716 @@ enum DepthChange {
     Decreased,
 }
 
-fn create_windows(measurements : &Vec<i64>, window_size : usize) -> Vec<i64> {
-    let mut windows = vec![];
-
-    for i in 0..(measurements.len() - window_size + 1) {
-        let sliding_window = &measurements.as_slice()[i..i+window_size];
-        let mut sum = 0;
-        for window in sliding_window.iter() {
-            sum = sum + window;
-        }
-        windows.push(sum);
-    }
-
-    windows
-}
-
-fn calculate_depth_changes(measurements : &Vec<i64>, window_size : usize) -> Vec<DepthChange> {
-    let mut previous_measurement : Option<i64> = None;
-    let mut depth_changes = vec![];
-
-    let windows = create_windows(measurements, window_size);
-    for measurement in windows.iter() {
-        depth_changes.push(match previous_measurement {
+impl DepthChange {
+    fn between<T : PartialOrd>(previous : Option<T>, current : T) -> Self {
+        match previous {
             Some(previous) => {
-                if measurement == &previous {
+                if current == previous {
                     DepthChange::NoChange
-                } else if measurement > &previous {
+                } else if current > previous {
                     DepthChange::Increased
                 } else {
                     DepthChange::Decreased
                 }
             },
             None => DepthChange::NotApplicable,
+        }
+    }
+}
+
+/// Lazily compares each value with the one before it. The first value has
+/// nothing to compare against and is reported as `NotApplicable`.
+struct DepthChanges<I : Iterator> {
+    values : I,
+    previous : Option<I::Item>,
+}
+
+impl<T : PartialOrd + Copy, I : Iterator<Item = T>> DepthChanges<I> {
+    fn new(values : I) -> Self {
+        DepthChanges {
+            values,
+            previous: None,
+        }
+    }
+}
+
+impl<T : PartialOrd + Copy, I : Iterator<Item = T>> Iterator for DepthChanges<I> {
+    type Item = DepthChange;
+
+    fn next(&mut self) -> Option<DepthChange> {
+        let current = self.values.next()?;
+        let change = DepthChange::between(self.previous, current);
+        self.previous = Some(current);
+        Some(change)
+    }
+}
+
+#[cfg(test)]
+fn depth_changes<I : IntoIterator<Item = i64>>(readings : I, window_size : usize) -> DepthChanges<SlidingSums<I::IntoIter>> {
+    depth_changes_with(readings, window_size, Sum::default())
+}
+
+fn depth_changes_with<I : IntoIterator<Item = i64>, A : Aggregator>(readings : I, window_size : usize, aggregator : A) -> DepthChanges<SlidingWindows<I::IntoIter, A>> {
+    DepthChanges::new(SlidingWindows::new(readings.into_iter(), window_size, aggregator))
+}
+
+fn count_depth_increases<I : IntoIterator<Item = i64>>(readings : I, window_size : usize) -> i64 {
+    count_depth_increases_with(readings, window_size, Sum::default())
+}
+
+fn count_depth_increases_with<I : IntoIterator<Item = i64>, A : Aggregator>(readings : I, window_size : usize, aggregator : A) -> i64 {
+    depth_changes_with(readings, window_size, aggregator)
+        .filter(|depth_change| depth_change == &DepthChange::Increased)
+        .count() as i64
+}
+
+/// Counts depth increases from lines of readings as they are parsed, failing
+/// on the first line that isn't a number.
+fn count_depth_increases_in<'a, I : Iterator<Item = &'a str>>(lines : I, window_size : usize) -> Result<i64> {
+    Ok(itertools::process_results(lines.map(str::parse::<i64>), |readings| count_depth_increases(readings, window_size))?)
+}
+
+fn find_depth_increases(measurements : &[i64], window_size : usize) -> i64 {
+    count_depth_increases(measurements.iter().copied(), window_size)
+}
+
+/// Reduces a window of readings to the single value that depth changes are
+/// computed from. `push` and `pop` are called as readings enter and leave the
+/// window so aggregators can keep running state; `value` is only asked for
+/// once the window is full.
+trait Aggregator {
+    type Value : PartialOrd + Copy;
+
+    fn push(&mut self, _reading : i64) {}
+    fn pop(&mut self, _reading : i64) {}
+    fn value(&self, window : &VecDeque<i64>) -> Self::Value;
+}
+
+impl<V : PartialOrd + Copy> Aggregator for Box<dyn Aggregator<Value = V>> {
+    type Value = V;
+
+    fn push(&mut self, reading : i64) {
+        self.as_mut().push(reading)
+    }
+
+    fn pop(&mut self, reading : i64) {
+        self.as_mut().pop(reading)
+    }
+
+    fn value(&self, window : &VecDeque<i64>) -> V {
+        self.as_ref().value(window)
+    }
+}
+
+/// Every full window of `window_size` consecutive readings, reduced by an
+/// `Aggregator` in O(1) per reading for those that keep running state. Inputs
+/// shorter than the window yield nothing, and a window size of zero is
+/// treated as empty input.
+struct SlidingWindows<I, A> {
+    readings : I,
+    window : VecDeque<i64>,
+    window_size : usize,
+    aggregator : A,
+}
+
+type SlidingSums<I> = SlidingWindows<I, Sum>;
+
+impl<I : Iterator<Item = i64>, A : Aggregator> SlidingWindows<I, A> {
+    fn new(readings : I, window_size : usize, aggregator : A) -> Self {
+        SlidingWindows {
+            readings,
+            window: VecDeque::with_capacity(window_size),
+            window_size,
+            aggregator,
+        }
+    }
+}
+
+impl<I : Iterator<Item = i64>> SlidingSums<I> {
+    fn sums(readings : I, window_size : usize) -> Self {
+        SlidingWindows::new(readings, window_size, Sum::default())
+    }
+}
+
+impl<I : Iterator<Item = i64>, A : Aggregator> Iterator for SlidingWindows<I, A> {
+    type Item = A::Value;
+
+    fn next(&mut self) -> Option<A::Value> {
+        if self.window_size == 0 {
+            return None;
+        }
+        if self.window.len() == self.window_size {
+            if let Some(oldest) = self.window.pop_front() {
+                self.aggregator.pop(oldest);
+            }
+        }
+        while self.window.len() < self.window_size {
+            let reading = self.readings.next()?;
+            self.aggregator.push(reading);
+            self.window.push_back(reading);
+        }
+        Some(self.aggregator.value(&self.window))
+    }
+}
+
+#[derive(Default)]
+struct Sum {
+    sum : i64,
+}
+
+impl Aggregator for Sum {
+    type Value = i64;
+
+    fn push(&mut self, reading : i64) {
+        self.sum += reading;
+    }
+
+    fn pop(&mut self, reading : i64) {
+        self.sum -= reading;
+    }
+
+    fn value(&self, _window : &VecDeque<i64>) -> i64 {
+        self.sum
+    }
+}
+
+/// Any aggregator's values as floats, so they can be compared side by side.
+struct AsFloat<A>(A);
+
+impl<A : Aggregator<Value = i64>> Aggregator for AsFloat<A> {
+    type Value = f64;
+
+    fn push(&mut self, reading : i64) {
+        self.0.push(reading);
+    }
+
+    fn pop(&mut self, reading : i64) {
+        self.0.pop(reading);
+    }
+
+    fn value(&self, window : &VecDeque<i64>) -> f64 {
+        self.0.value(window) as f64
+    }
+}
+
+#[derive(Default)]
+struct Mean {
+    sum : Sum,
+}
+
+impl Aggregator for Mean {
+    type Value = f64;
+
+    fn push(&mut self, reading : i64) {
+        self.sum.push(reading);
+    }
+
+    fn pop(&mut self, reading : i64) {
+        self.sum.pop(reading);
+    }
+
+    fn value(&self, window : &VecDeque<i64>) -> f64 {
+        self.sum.value(window) as f64 / window.len() as f64
+    }
+}
+
+struct Median;
+
+impl Aggregator for Median {
+    type Value = f64;
+
+    fn value(&self, window : &VecDeque<i64>) -> f64 {
+        let mut sorted : Vec<i64> = window.iter().copied().collect();
+        sorted.sort_unstable();
+        let middle = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] as f64 + sorted[middle] as f64) / 2.0
+        } else {
+            sorted[middle] as f64
+        }
+    }
+}
+
+/// Monotonic queue of window candidates, so the extreme of the window is
+/// always at the front. `keep(candidate, reading)` decides whether an older
+/// candidate survives a newer reading.
+struct Extreme {
+    candidates : VecDeque<i64>,
+    keep : fn(i64, i64) -> bool,
+}
+
+impl Extreme {
+    fn min() -> Self {
+        Extreme {
+            candidates: VecDeque::new(),
+            keep: |candidate, reading| candidate <= reading,
+        }
+    }
+
+    fn max() -> Self {
+        Extreme {
+            candidates: VecDeque::new(),
+            keep: |candidate, reading| candidate >= reading,
+        }
+    }
+}
+
+impl Aggregator for Extreme {
+    type Value = f64;
+
+    fn push(&mut self, reading : i64) {
+        while let Some(back) = self.candidates.back() {
+            if (self.keep)(*back, reading) {
+                break;
+            }
+            self.candidates.pop_back();
+        }
+        self.candidates.push_back(reading);
+    }
+
+    fn pop(&mut self, reading : i64) {
+        if self.candidates.front() == Some(&reading) {
+            self.candidates.pop_front();
+        }
+    }
+
+    fn value(&self, _window : &VecDeque<i64>) -> f64 {
+        self.candidates.front().copied().unwrap_or_default() as f64
+    }
+}
+
+/// Exponentially weighted average over every reading seen so far. Unlike the
+/// other aggregators it ignores readings leaving the window, so the window
+/// size only controls how many readings are taken before the first value is
+/// produced.
+struct ExponentiallyWeighted {
+    alpha : f64,
+    average : Option<f64>,
+}
+
+impl ExponentiallyWeighted {
+    fn new(alpha : f64) -> Self {
+        ExponentiallyWeighted {
+            alpha,
+            average: None,
+        }
+    }
+}
+
+impl Aggregator for ExponentiallyWeighted {
+    type Value = f64;
+
+    fn push(&mut self, reading : i64) {
+        let reading = reading as f64;
+        self.average = Some(match self.average {
+            Some(average) => self.alpha * reading + (1.0 - self.alpha) * average,
+            None => reading,
         });
+    }
 
-        previous_measurement = Some(*measurement);
+    fn value(&self, _window : &VecDeque<i64>) -> f64 {
+        self.average.unwrap_or_default()
     }
+}
 
-    depth_changes
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum AggregatorKind {
+    Sum,
+    Mean,
+    Median,
+    Min,
+    Max,
+    /// The smoothing factor. Doesn't use the window beyond the first value,
+    /// see `ExponentiallyWeighted`.
+    ExponentiallyWeighted(f64),
 }
 
-fn find_depth_increases(measurements : &Vec<i64>, window_size : usize) -> i64 {
-    let depth_changes = calculate_depth_changes(measurements, window_size);
+impl AggregatorKind {
+    fn all() -> Vec<AggregatorKind> {
+        vec![
+            AggregatorKind::Sum,
+            AggregatorKind::Mean,
+            AggregatorKind::Median,
+            AggregatorKind::Min,
+            AggregatorKind::Max,
+            AggregatorKind::ExponentiallyWeighted(0.5),
+        ]
+    }
 
-    let mut increase_count = 0;
+    fn aggregator(&self) -> Box<dyn Aggregator<Value = f64>> {
+        match self {
+            AggregatorKind::Sum => Box::new(AsFloat(Sum::default())),
+            AggregatorKind::Mean => Box::new(Mean::default()),
+            AggregatorKind::Median => Box::new(Median),
+            AggregatorKind::Min => Box::new(Extreme::min()),
+            AggregatorKind::Max => Box::new(Extreme::max()),
+            AggregatorKind::ExponentiallyWeighted(alpha) => Box::new(ExponentiallyWeighted::new(*alpha)),
+        }
+    }
+}
 
-    for depth_change in depth_changes.iter() {
-        if depth_change == &DepthChange::Increased {
-            increase_count = increase_count + 1;
+impl std::fmt::Display for AggregatorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AggregatorKind::Sum => write!(f, "sum"),
+            AggregatorKind::Mean => write!(f, "mean"),
+            AggregatorKind::Median => write!(f, "median"),
+            AggregatorKind::Min => write!(f, "min"),
+            AggregatorKind::Max => write!(f, "max"),
+            AggregatorKind::ExponentiallyWeighted(alpha) => write!(f, "ewma({})", alpha),
         }
     }
+}
 
-    increase_count
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum AnomalyReason {
+    ZScore,
+    Jump,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Anomaly {
+    index : usize,
+    reason : AnomalyReason,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum AnomalyHandling {
+    Keep,
+    Exclude,
+    Interpolate,
+}
+
+/// Flags readings that look like sensor glitches rather than real depth
+/// changes. A reading is an outlier when its z-score against those of the
+/// previous `window_size` readings that were not flagged exceeds
+/// `z_score_threshold`,
+/// or when it jumps more than `jump_threshold` away from both the previous
+/// reading and the last reading that was not flagged. Checking both means that
+/// only the first reading of a genuine level shift is flagged, while a spike
+/// and its return to normal only flags the spike. Before any reading has been
+/// accepted, the median of the first `window_size` readings stands in for the
+/// last one, so a spike at the very start is caught too. Either check is
+/// disabled with `None`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct AnomalyDetector {
+    window_size : usize,
+    z_score_threshold : Option<f64>,
+    jump_threshold : Option<i64>,
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        AnomalyDetector {
+            window_size: 10,
+            // depths drift steadily, so a lower threshold flags ordinary
+            // steep descents
+            z_score_threshold: Some(6.0),
+            jump_threshold: Some(100),
+        }
+    }
+}
+
+impl AnomalyDetector {
+    fn z_score(&self, measurements : &[i64], flagged : &[bool], index : usize) -> Option<f64> {
+        if self.window_size < 2 || index < self.window_size {
+            return None;
+        }
+        let accepted : Vec<i64> = (index - self.window_size..index)
+            .filter(|i| !flagged[*i])
+            .map(|i| measurements[i])
+            .collect();
+        if accepted.len() < 2 {
+            return None;
+        }
+        let mean = accepted.iter().sum::<i64>() as f64 / accepted.len() as f64;
+        let variance = accepted.iter()
+            .map(|reading| (*reading as f64 - mean).powi(2))
+            .sum::<f64>() / accepted.len() as f64;
+        if variance == 0.0 {
+            None
+        } else {
+            Some((measurements[index] as f64 - mean) / variance.sqrt())
+        }
+    }
+
+    fn detect(&self, measurements : &[i64]) -> Vec<Anomaly> {
+        let mut anomalies = vec![];
+        let mut flagged = vec![false; measurements.len()];
+        let mut first : Vec<i64> = measurements.iter().take(self.window_size.max(1)).copied().collect();
+        first.sort_unstable();
+        let mut last_accepted = first.get(first.len() / 2).copied();
+
+        for (index, reading) in measurements.iter().enumerate() {
+            let is_outlier = match (self.z_score_threshold, self.z_score(measurements, &flagged, index)) {
+                (Some(threshold), Some(z_score)) => z_score.abs() > threshold,
+                _ => false,
+            };
+            let is_jump = match (self.jump_threshold, last_accepted) {
+                (Some(threshold), Some(last_accepted)) => {
+                    let from_previous = index.checked_sub(1).is_none_or(|previous| (reading - measurements[previous]).abs() > threshold);
+                    from_previous && (reading - last_accepted).abs() > threshold
+                },
+                _ => false,
+            };
+
+            if is_jump {
+                anomalies.push(Anomaly {
+                    index,
+                    reason: AnomalyReason::Jump,
+                });
+                flagged[index] = true;
+            } else if is_outlier {
+                anomalies.push(Anomaly {
+                    index,
+                    reason: AnomalyReason::ZScore,
+                });
+                flagged[index] = true;
+            } else {
+                last_accepted = Some(*reading);
+            }
+        }
+
+        anomalies
+    }
+
+    /// Returns the measurements with the flagged readings handled. Interpolated
+    /// readings are placed on the line between the closest accepted readings on
+    /// either side, or copy the closest one at the ends of the series.
+    fn clean(&self, measurements : &[i64], handling : AnomalyHandling) -> Vec<i64> {
+        let mut flagged = vec![false; measurements.len()];
+        for anomaly in self.detect(measurements).iter() {
+            flagged[anomaly.index] = true;
+        }
+
+        match handling {
+            AnomalyHandling::Keep => measurements.to_vec(),
+            AnomalyHandling::Exclude => measurements.iter()
+                .zip(flagged.iter())
+                .filter(|(_, flagged)| !**flagged)
+                .map(|(reading, _)| *reading)
+                .collect(),
+            AnomalyHandling::Interpolate => (0..measurements.len())
+                .map(|index| {
+                    if !flagged[index] {
+                        return measurements[index];
+                    }
+                    let before = (0..index).rev().find(|i| !flagged[*i]);
+                    let after = (index + 1..measurements.len()).find(|i| !flagged[*i]);
+                    match (before, after) {
+                        (Some(before), Some(after)) => {
+                            let from = measurements[before];
+                            let to = measurements[after];
+                            from + (to - from) * (index - before) as i64 / (after - before) as i64
+                        },
+                        (Some(closest), None) | (None, Some(closest)) => measurements[closest],
+                        (None, None) => measurements[index],
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+struct DepthChangeCounts {
+    not_applicable : usize,
+    no_change : usize,
+    increased : usize,
+    decreased : usize,
+}
+
+impl DepthChangeCounts {
+    fn push(&mut self, depth_change : DepthChange) {
+        match depth_change {
+            DepthChange::NotApplicable => self.not_applicable += 1,
+            DepthChange::NoChange => self.no_change += 1,
+            DepthChange::Increased => self.increased += 1,
+            DepthChange::Decreased => self.decreased += 1,
+        }
+    }
+}
+
+/// Consecutive changes in the same direction. `start` is the index of the
+/// window the run starts from and `length` is the number of changes in it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Run {
+    start : usize,
+    length : usize,
+}
+
+impl Run {
+    fn extend(run : Option<Run>, index : usize) -> Run {
+        match run {
+            Some(run) => Run {
+                length: run.length + 1,
+                ..run
+            },
+            None => Run {
+                start: index - 1,
+                length: 1,
+            },
+        }
+    }
+
+    fn longest(longest : Option<Run>, current : Option<Run>) -> Option<Run> {
+        match (longest, current) {
+            (Some(longest), Some(current)) if current.length > longest.length => Some(current),
+            (None, current) => current,
+            (longest, _) => longest,
+        }
+    }
+}
+
+/// A single change between two windows. `index` is the window the change
+/// ends on and `amount` is always positive, for drops as well as rises.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Step {
+    index : usize,
+    amount : i64,
+}
+
+impl Step {
+    fn largest(largest : Option<Step>, index : usize, amount : i64) -> Option<Step> {
+        match largest {
+            Some(largest) if largest.amount >= amount => Some(largest),
+            _ => Some(Step {
+                index,
+                amount,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+struct WindowReport {
+    window_size : usize,
+    counts : DepthChangeCounts,
+    longest_increasing_run : Option<Run>,
+    longest_decreasing_run : Option<Run>,
+    largest_rise : Option<Step>,
+    largest_drop : Option<Step>,
+}
+
+impl WindowReport {
+    fn new<I : IntoIterator<Item = i64>>(readings : I, window_size : usize) -> Self {
+        let mut report = WindowReport {
+            window_size,
+            ..WindowReport::default()
+        };
+        let mut previous = None;
+        let mut increasing_run = None;
+        let mut decreasing_run = None;
+
+        for (index, sum) in SlidingSums::sums(readings.into_iter(), window_size).enumerate() {
+            let depth_change = DepthChange::between(previous, sum);
+            report.counts.push(depth_change);
+
+            match depth_change {
+                DepthChange::Increased => {
+                    increasing_run = Some(Run::extend(increasing_run, index));
+                    decreasing_run = None;
+                    report.largest_rise = Step::largest(report.largest_rise, index, sum - previous.unwrap_or(sum));
+                },
+                DepthChange::Decreased => {
+                    decreasing_run = Some(Run::extend(decreasing_run, index));
+                    increasing_run = None;
+                    report.largest_drop = Step::largest(report.largest_drop, index, previous.unwrap_or(sum) - sum);
+                },
+                _ => {
+                    increasing_run = None;
+                    decreasing_run = None;
+                },
+            }
+            report.longest_increasing_run = Run::longest(report.longest_increasing_run, increasing_run);
+            report.longest_decreasing_run = Run::longest(report.longest_decreasing_run, decreasing_run);
+
+            previous = Some(sum);
+        }
+
+        report
+    }
+
+    fn to_json(&self) -> String {
+        fn run(run : Option<Run>) -> String {
+            match run {
+                Some(run) => format!("{{\"start\":{},\"length\":{}}}", run.start, run.length),
+                None => "null".to_string(),
+            }
+        }
+        fn step(step : Option<Step>) -> String {
+            match step {
+                Some(step) => format!("{{\"index\":{},\"amount\":{}}}", step.index, step.amount),
+                None => "null".to_string(),
+            }
+        }
+
+        format!(
+            "{{\"window_size\":{},\"not_applicable\":{},\"no_change\":{},\"increased\":{},\"decreased\":{},\"longest_increasing_run\":{},\"longest_decreasing_run\":{},\"largest_rise\":{},\"largest_drop\":{}}}",
+            self.window_size,
+            self.counts.not_applicable,
+            self.counts.no_change,
+            self.counts.increased,
+            self.counts.decreased,
+            run(self.longest_increasing_run),
+            run(self.longest_decreasing_run),
+            step(self.largest_rise),
+            step(self.largest_drop),
+        )
+    }
+}
+
+/// Depth change statistics for the same readings compared across several
+/// window sizes.
+#[derive(Debug, PartialEq)]
+struct DepthReport {
+    windows : Vec<WindowReport>,
+}
+
+impl DepthReport {
+    fn new(measurements : &[i64], window_sizes : &[usize]) -> Self {
+        DepthReport {
+            windows: window_sizes.iter()
+                .map(|window_size| WindowReport::new(measurements.iter().copied(), *window_size))
+                .collect(),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        format!("[{}]", self.windows.iter().map(WindowReport::to_json).collect::<Vec<String>>().join(","))
+    }
+}
+
+impl std::fmt::Display for DepthReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn run(run : Option<Run>) -> String {
+            run.map(|run| format!("{} @ {}", run.length, run.start)).unwrap_or_else(|| "-".to_string())
+        }
+        fn step(step : Option<Step>) -> String {
+            step.map(|step| format!("{} @ {}", step.amount, step.index)).unwrap_or_else(|| "-".to_string())
+        }
+
+        writeln!(f, "{:>6} | {:>5} | {:>9} | {:>9} | {:>9} | {:>16} | {:>16} | {:>12} | {:>12}",
+            "window", "n/a", "no change", "increased", "decreased", "longest increase", "longest decrease", "largest rise", "largest drop")?;
+        for window in self.windows.iter() {
+            writeln!(f, "{:>6} | {:>5} | {:>9} | {:>9} | {:>9} | {:>16} | {:>16} | {:>12} | {:>12}",
+                window.window_size,
+                window.counts.not_applicable,
+                window.counts.no_change,
+                window.counts.increased,
+                window.counts.decreased,
+                run(window.longest_increasing_run),
+                run(window.longest_decreasing_run),
+                step(window.largest_rise),
+                step(window.largest_drop))?;
+        }
+        Ok(())
+    }
 }
 
 #[test]
 fn test_depth_increases_part1() {
-    let depth_increase_count = find_depth_increases(&vec![
+    let depth_increase_count = count_depth_increases([
         199,
         200,
         208,
@@ -93,7 +785,7 @@ fn test_depth_increases_part1() {
 
 #[test]
 fn test_depth_increases_part2() {
-    let depth_increase_count = find_depth_increases(&vec![
+    let depth_increase_count = count_depth_increases([
         199,
         200,
         208,
@@ -108,3 +800,213 @@ fn test_depth_increases_part2() {
 
     assert_eq!(depth_increase_count, 5);
 }
+
+#[test]
+fn test_sliding_sums() {
+    let sums : Vec<i64> = SlidingSums::sums(vec![199, 200, 208, 210, 200].into_iter(), 3).collect();
+
+    assert_eq!(sums, vec![607, 618, 618]);
+}
+
+#[test]
+fn test_depth_changes() {
+    let changes : Vec<DepthChange> = depth_changes(vec![607, 618, 618, 617], 1).collect();
+
+    assert_eq!(changes, vec![
+        DepthChange::NotApplicable,
+        DepthChange::Increased,
+        DepthChange::NoChange,
+        DepthChange::Decreased,
+    ]);
+}
+
+#[test]
+fn test_input_shorter_than_window() {
+    assert_eq!(count_depth_increases([199, 200], 3), 0);
+    assert_eq!(count_depth_increases([], 1), 0);
+    assert_eq!(depth_changes(vec![199, 200], 3).count(), 0);
+    assert_eq!(depth_changes(vec![199, 200], 0).count(), 0);
+}
+
+#[test]
+fn test_streaming_large_input() {
+    let readings : Vec<i64> = (0..1_000_000_i64).map(|i| (i % 7) * 3 + i / 10).collect();
+    let expected = readings.windows(3)
+        .map(|window| window.iter().sum::<i64>())
+        .collect::<Vec<i64>>()
+        .windows(2)
+        .filter(|pair| pair[1] > pair[0])
+        .count() as i64;
+
+    assert_eq!(count_depth_increases(readings.into_iter(), 3), expected);
+}
+
+#[test]
+fn test_depth_increases_puzzle() -> Result<()> {
+    assert_eq!(count_depth_increases_in(input_lines(1)?, 1)?, 1462);
+    assert_eq!(count_depth_increases_in(input_lines(1)?, 3)?, 1497);
+    assert_eq!(count_depth_increases_in(["1", "3", "2", "4"].into_iter(), 1)?, 2);
+    assert!(count_depth_increases_in(["1", "x", "2"].into_iter(), 1).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_window_report() {
+    let measurements = vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263];
+    let report = DepthReport::new(&measurements, &[1, 3]);
+
+    assert_eq!(report.windows, vec![
+        WindowReport {
+            window_size: 1,
+            counts: DepthChangeCounts {
+                not_applicable: 1,
+                no_change: 0,
+                increased: 7,
+                decreased: 2,
+            },
+            longest_increasing_run: Some(Run { start: 0, length: 3 }),
+            longest_decreasing_run: Some(Run { start: 3, length: 1 }),
+            largest_rise: Some(Step { index: 6, amount: 33 }),
+            largest_drop: Some(Step { index: 4, amount: 10 }),
+        },
+        WindowReport {
+            window_size: 3,
+            counts: DepthChangeCounts {
+                not_applicable: 1,
+                no_change: 1,
+                increased: 5,
+                decreased: 1,
+            },
+            longest_increasing_run: Some(Run { start: 3, length: 4 }),
+            longest_decreasing_run: Some(Run { start: 2, length: 1 }),
+            largest_rise: Some(Step { index: 5, amount: 69 }),
+            largest_drop: Some(Step { index: 3, amount: 1 }),
+        },
+    ]);
+}
+
+#[test]
+fn test_window_report_output() {
+    let report = DepthReport::new(&[3, 1], &[1, 2]);
+
+    assert_eq!(report.to_json(), concat!(
+        "[{\"window_size\":1,\"not_applicable\":1,\"no_change\":0,\"increased\":0,\"decreased\":1,",
+        "\"longest_increasing_run\":null,\"longest_decreasing_run\":{\"start\":0,\"length\":1},",
+        "\"largest_rise\":null,\"largest_drop\":{\"index\":1,\"amount\":2}},",
+        "{\"window_size\":2,\"not_applicable\":1,\"no_change\":0,\"increased\":0,\"decreased\":0,",
+        "\"longest_increasing_run\":null,\"longest_decreasing_run\":null,",
+        "\"largest_rise\":null,\"largest_drop\":null}]",
+    ));
+
+    let table = report.to_string();
+    assert_eq!(table.lines().count(), 3);
+    assert!(table.lines().nth(1).unwrap().contains("1 @ 0"));
+    assert!(table.lines().nth(1).unwrap().contains("2 @ 1"));
+}
+
+#[test]
+fn test_aggregated_windows() {
+    let measurements = [199, 200, 208, 210, 200, 207];
+    let aggregate = |aggregator : Box<dyn Aggregator<Value = f64>>| -> Vec<f64> {
+        SlidingWindows::new(measurements.iter().copied(), 3, aggregator).collect()
+    };
+
+    assert_eq!(aggregate(AggregatorKind::Sum.aggregator()), vec![607.0, 618.0, 618.0, 617.0]);
+    assert_eq!(aggregate(AggregatorKind::Median.aggregator()), vec![200.0, 208.0, 208.0, 207.0]);
+    assert_eq!(aggregate(AggregatorKind::Min.aggregator()), vec![199.0, 200.0, 200.0, 200.0]);
+    assert_eq!(aggregate(AggregatorKind::Max.aggregator()), vec![208.0, 210.0, 210.0, 210.0]);
+    assert_eq!(aggregate(AggregatorKind::ExponentiallyWeighted(0.5).aggregator()), vec![203.75, 206.875, 203.4375, 205.21875]);
+    assert_eq!(SlidingWindows::new(vec![1, 2, 3, 4].into_iter(), 2, Median).collect::<Vec<f64>>(), vec![1.5, 2.5, 3.5]);
+    assert_eq!(SlidingWindows::new(vec![i64::MAX, i64::MAX].into_iter(), 2, Median).collect::<Vec<f64>>(), vec![i64::MAX as f64]);
+}
+
+#[test]
+fn test_depth_increases_with_aggregators() {
+    let measurements = vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263];
+
+    assert_eq!(count_depth_increases_with(measurements.iter().copied(), 1, Sum::default()), 7);
+    assert_eq!(count_depth_increases_with(measurements.iter().copied(), 3, Sum::default()), 5);
+    assert_eq!(count_depth_increases_with(measurements.iter().copied(), 3, Mean::default()), 5);
+    assert_eq!(count_depth_increases_with(measurements.iter().copied(), 3, Median), 4);
+    assert_eq!(count_depth_increases_with(measurements.iter().copied(), 3, Extreme::min()), 4);
+    assert_eq!(count_depth_increases_with(measurements.iter().copied(), 3, Extreme::max()), 3);
+    assert_eq!(count_depth_increases_with(measurements.iter().copied(), 5, Sum::default()), count_depth_increases(measurements.clone(), 5));
+}
+
+#[test]
+fn test_jump_anomalies() {
+    let detector = AnomalyDetector {
+        window_size: 5,
+        z_score_threshold: None,
+        jump_threshold: Some(100),
+    };
+    let measurements = vec![199, 200, 208, 210, 200, 5000, 240, 269, 260, 263];
+
+    assert_eq!(detector.detect(&measurements), vec![Anomaly { index: 5, reason: AnomalyReason::Jump }]);
+    assert_eq!(find_depth_increases(&detector.clean(&measurements, AnomalyHandling::Keep), 1), 6);
+    assert_eq!(detector.clean(&measurements, AnomalyHandling::Exclude), vec![199, 200, 208, 210, 200, 240, 269, 260, 263]);
+    assert_eq!(detector.clean(&measurements, AnomalyHandling::Interpolate), vec![199, 200, 208, 210, 200, 220, 240, 269, 260, 263]);
+    assert_eq!(find_depth_increases(&detector.clean(&measurements, AnomalyHandling::Interpolate), 1), 7);
+}
+
+#[test]
+fn test_jump_anomalies_level_shift() {
+    let detector = AnomalyDetector {
+        window_size: 5,
+        z_score_threshold: None,
+        jump_threshold: Some(100),
+    };
+    let measurements = vec![199, 200, 208, 510, 500, 507, 540];
+
+    assert_eq!(detector.detect(&measurements), vec![Anomaly { index: 3, reason: AnomalyReason::Jump }]);
+    assert_eq!(detector.clean(&measurements, AnomalyHandling::Interpolate), vec![199, 200, 208, 354, 500, 507, 540]);
+}
+
+#[test]
+fn test_z_score_anomalies() {
+    let detector = AnomalyDetector {
+        window_size: 5,
+        z_score_threshold: Some(10.0),
+        jump_threshold: None,
+    };
+    let measurements = vec![199, 200, 208, 210, 200, 207, 240, -50, 260, 263];
+
+    assert_eq!(detector.detect(&measurements), vec![Anomaly { index: 7, reason: AnomalyReason::ZScore }]);
+    assert_eq!(detector.clean(&measurements, AnomalyHandling::Interpolate), vec![199, 200, 208, 210, 200, 207, 240, 250, 260, 263]);
+    assert_eq!(find_depth_increases(&detector.clean(&measurements, AnomalyHandling::Interpolate), 3), 5);
+}
+
+#[test]
+fn test_anomalies_at_edges() {
+    let detector = AnomalyDetector {
+        window_size: 5,
+        z_score_threshold: None,
+        jump_threshold: Some(100),
+    };
+    let measurements = vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 2630];
+
+    assert_eq!(detector.detect(&measurements), vec![Anomaly { index: 9, reason: AnomalyReason::Jump }]);
+    assert_eq!(detector.clean(&measurements, AnomalyHandling::Interpolate)[9], 260);
+    assert_eq!(detector.detect(&[]), vec![]);
+
+    let measurements = vec![5000, 200, 208, 210, 200, 207];
+    assert_eq!(detector.detect(&measurements), vec![Anomaly { index: 0, reason: AnomalyReason::Jump }]);
+    assert_eq!(detector.clean(&measurements, AnomalyHandling::Interpolate)[0], 200);
+}
+
+#[test]
+fn test_flagged_readings_leave_the_z_score_window() {
+    let detector = AnomalyDetector {
+        window_size: 4,
+        z_score_threshold: Some(3.0),
+        jump_threshold: None,
+    };
+    // with the spike left in the window, the reading after it would look
+    // perfectly normal and the one after that wouldn't be compared fairly
+    let measurements = vec![200, 201, 199, 200, 900, 201, 260];
+
+    assert_eq!(detector.detect(&measurements), vec![
+        Anomaly { index: 4, reason: AnomalyReason::ZScore },
+        Anomaly { index: 6, reason: AnomalyReason::ZScore },
+    ]);
+}
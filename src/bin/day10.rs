@@ -1,4 +1,5 @@
 use aoc2021::input::{InputFileError, load_input};
+use aoc2021::Counter;
 use core::str::FromStr;
 
 fn main() -> anyhow::Result<()> {
@@ -93,17 +94,10 @@ pub trait Day10 {
 impl Day10 for Vec<Line> {
 
     fn part1(&self) -> i64 {
-        let mut symbols = std::collections::HashMap::new();
-
-        for line in self.iter() {
-            match line.check_syntax_error() {
-                None => {},
-                Some(error) => {
-                    *symbols.entry(error.found).or_insert(0) += 1;
-                }
-            }
-
-        }
+        let symbols : Counter<char> = self.iter()
+            .filter_map(|line| line.check_syntax_error())
+            .map(|error| error.found)
+            .collect();
 
         let mut total = 0;
         for (symbol, count) in symbols.iter() {
@@ -113,7 +107,7 @@ impl Day10 for Vec<Line> {
                 '}' => 1197,
                 '>' => 25137,
                 _ => 0,
-            } * count
+            } * count as i64
         }
 
         total
@@ -1,111 +1,732 @@
-use aoc2021::input::{InputFileError, load_input};
+use aoc2021::input::{InputFileError, load_input_text};
+use std::collections::HashMap;
 use std::str::FromStr;
+use thiserror::Error;
 
 fn main() -> anyhow::Result<()> {
-    let commands : Vec<MovementCommand> = load_input(2)?;
+    let script : Script = load_input_text(2)?;
 
-    let mut part1 = Position::default();
-    part1.r#move(&commands);
+    let mut policy = SurfacePolicy::Error;
+    let mut outputs = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--clamp" => policy = SurfacePolicy::ClampToSurface,
+            "--fly" => policy = SurfacePolicy::AllowFlying,
+            _ => outputs.push(arg),
+        }
+    }
+
+    let mut part1 = Submarine::new(Part1Movement);
+    part1.run(&script, policy)?;
     println!("part1: {}", part1.result());
 
-    let mut part2 = PositionPart2::default();
-    part2.r#move(&commands);
+    let mut part2 = Submarine::new(Part2Movement);
+    part2.run(&script, policy)?;
     println!("part2: {}", part2.result());
 
+    for arg in outputs {
+        let trajectory = Submarine::new(Part2Movement).record(&script.commands()?, policy)?;
+        match arg.as_str() {
+            "--csv" => print!("{}", trajectory.to_csv()),
+            "--svg" => println!("{}", trajectory.to_svg()),
+            "--stats" => {
+                println!("max depth: {}", trajectory.max_depth());
+                println!("distance travelled: {:.2}", trajectory.distance_travelled());
+            },
+            _ => if let Some(depth) = arg.strip_prefix("--reach=") {
+                match trajectory.step_reaching_depth(depth.parse()?) {
+                    Some(step) => println!("depth {} reached at step {}", depth, step),
+                    None => println!("depth {} never reached", depth),
+                }
+            } else if let Some((horizontal, depth)) = arg.strip_prefix("--solve=").and_then(|target| target.split_once(',')) {
+                let (horizontal, depth) = (horizontal.parse()?, depth.parse()?);
+                print_solution("part1", Part1Movement, horizontal, depth)?;
+                print_solution("part2", Part2Movement, horizontal, depth)?;
+            } else {
+                return Err(anyhow::anyhow!("unknown argument {}", arg));
+            },
+        }
+    }
+
     Ok(())
 }
 
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, PartialEq, Debug, Clone, Copy)]
 struct Position {
-    horizontal : usize,
-    depth : usize,
+    horizontal : i64,
+    depth : i64,
 }
 
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, PartialEq, Debug, Clone, Copy)]
 struct PositionPart2 {
-    horizontal : usize,
-    depth : usize,
-    aim : usize,
+    horizontal : i64,
+    depth : i64,
+    aim : i64,
 }
 
-impl Position {
-    fn r#move(&mut self, commands : &Vec<MovementCommand>) {
-        for command in commands.into_iter() {
-            command.move_part1(self)
-        }
+/// The state a `MovementModel` moves around. Every state has to know its
+/// depth so surface policies can be applied to it.
+trait SubmarineState : Default + Copy {
+    fn depth_mut(&mut self) -> &mut i64;
+    fn point(&self) -> TrajectoryPoint;
+}
+
+impl SubmarineState for Position {
+    fn depth_mut(&mut self) -> &mut i64 {
+        &mut self.depth
     }
 
-    fn result(&self) -> usize {
-        self.horizontal * self.depth
+    fn point(&self) -> TrajectoryPoint {
+        TrajectoryPoint {
+            horizontal: self.horizontal,
+            depth: self.depth,
+            aim: None,
+        }
     }
 }
 
-impl PositionPart2 {
-    fn r#move(&mut self, commands : &Vec<MovementCommand>) {
-        for command in commands.into_iter() {
-            command.move_part2(self)
-        }
+impl SubmarineState for PositionPart2 {
+    fn depth_mut(&mut self) -> &mut i64 {
+        &mut self.depth
     }
 
-    fn result(&self) -> usize {
-        self.horizontal * self.depth
+    fn point(&self) -> TrajectoryPoint {
+        TrajectoryPoint {
+            horizontal: self.horizontal,
+            depth: self.depth,
+            aim: Some(self.aim),
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum MovementCommand {
-    Forward(usize),
-    Down(usize),
-    Up(usize),
+/// An interpretation of the movement commands, so the same parsed commands
+/// can be run against different submarines.
+trait MovementModel {
+    type State : SubmarineState;
+
+    fn apply(&self, state : &mut Self::State, command : &MovementCommand);
 }
 
-impl MovementCommand {
-    fn move_part1(&self, position : &mut Position) {
-        match self {
+/// Part 1: `down` and `up` change the depth directly.
+struct Part1Movement;
+
+/// Part 2: `down` and `up` change the aim, which is applied to the depth
+/// when moving.
+struct Part2Movement;
+
+impl MovementModel for Part1Movement {
+    type State = Position;
+
+    fn apply(&self, position : &mut Position, command : &MovementCommand) {
+        match command {
             MovementCommand::Forward(count) => {
-                position.horizontal += count
+                position.horizontal += *count as i64
             },
             MovementCommand::Down(count) => {
-                position.depth += count
+                position.depth += *count as i64
             },
             MovementCommand::Up(count) => {
-                position.depth -= count
+                position.depth -= *count as i64
+            },
+            MovementCommand::Back(count) => {
+                position.horizontal -= *count as i64
+            },
+            MovementCommand::Surface => {
+                position.depth = 0
             },
         }
     }
+}
 
-    fn move_part2(&self, position : &mut PositionPart2) {
-        match self {
+impl MovementModel for Part2Movement {
+    type State = PositionPart2;
+
+    fn apply(&self, position : &mut PositionPart2, command : &MovementCommand) {
+        match command {
             MovementCommand::Forward(count) => {
-                position.horizontal += count;
-                position.depth += position.aim * count;
+                position.horizontal += *count as i64;
+                position.depth += position.aim * *count as i64;
             },
             MovementCommand::Down(count) => {
-                position.aim += count;
+                position.aim += *count as i64;
             },
             MovementCommand::Up(count) => {
-                position.aim -= count;
+                position.aim -= *count as i64;
+            },
+            MovementCommand::Back(count) => {
+                position.horizontal -= *count as i64;
+                position.depth -= position.aim * *count as i64;
             },
+            MovementCommand::Surface => {
+                position.depth = 0;
+                position.aim = 0;
+            },
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+enum SolveError {
+    #[error("depth {0} is above the surface")]
+    AboveSurface(i64),
+}
+
+/// Movement models that can work backwards from a target position to the
+/// shortest list of commands reaching it from the starting position, without
+/// ever breaching the surface on the way.
+trait InverseMovement : MovementModel {
+    fn solve(&self, horizontal : i64, depth : i64) -> Result<Vec<MovementCommand>, SolveError>;
+}
+
+fn horizontal_command(horizontal : i64) -> Option<MovementCommand> {
+    match horizontal {
+        0 => None,
+        h if h > 0 => Some(MovementCommand::Forward(h as usize)),
+        h => Some(MovementCommand::Back(h.unsigned_abs() as usize)),
+    }
+}
+
+impl InverseMovement for Part1Movement {
+    /// Each command moves along a single axis, so one command per axis that
+    /// isn't already at the target is the best we can do.
+    fn solve(&self, horizontal : i64, depth : i64) -> Result<Vec<MovementCommand>, SolveError> {
+        if depth < 0 {
+            return Err(SolveError::AboveSurface(depth));
+        }
+        let mut commands : Vec<MovementCommand> = horizontal_command(horizontal).into_iter().collect();
+        if depth > 0 {
+            commands.push(MovementCommand::Down(depth as usize));
+        }
+        Ok(commands)
+    }
+}
+
+impl InverseMovement for Part2Movement {
+    /// Depth only changes when moving with a non-zero aim, and the aim starts
+    /// at zero. A single aim change followed by a single move reaches every
+    /// target whose depth is a multiple of its horizontal distance. Anything
+    /// else needs three commands: move all but one step, aim, and take the
+    /// last step. A target straight below the start moves forward one step
+    /// and comes back with the aim pointing up, which still dives on the way
+    /// back.
+    fn solve(&self, horizontal : i64, depth : i64) -> Result<Vec<MovementCommand>, SolveError> {
+        if depth < 0 {
+            return Err(SolveError::AboveSurface(depth));
+        }
+        if depth == 0 {
+            return Ok(horizontal_command(horizontal).into_iter().collect());
+        }
+        if horizontal != 0 && depth % horizontal == 0 {
+            let aim = depth / horizontal;
+            let turn = if aim > 0 {
+                MovementCommand::Down(aim as usize)
+            } else {
+                MovementCommand::Up(aim.unsigned_abs() as usize)
+            };
+            return Ok(vec![turn].into_iter().chain(horizontal_command(horizontal)).collect());
+        }
+
+        let depth = depth as usize;
+        let commands = if horizontal > 0 {
+            vec![MovementCommand::Forward(horizontal as usize - 1), MovementCommand::Down(depth), MovementCommand::Forward(1)]
+        } else if horizontal < 0 {
+            vec![MovementCommand::Back(horizontal.unsigned_abs() as usize - 1), MovementCommand::Up(depth), MovementCommand::Back(1)]
+        } else {
+            vec![MovementCommand::Forward(1), MovementCommand::Up(depth), MovementCommand::Back(1)]
+        };
+        Ok(commands)
+    }
+}
+
+fn print_solution<M : InverseMovement>(name : &str, model : M, horizontal : i64, depth : i64) -> anyhow::Result<()> {
+    let commands = model.solve(horizontal, depth)?;
+    if !verify(model, &commands, horizontal, depth)? {
+        return Err(anyhow::anyhow!("{} solution does not reach {},{}", name, horizontal, depth));
+    }
+    println!("{}: {}", name, commands.iter().map(ToString::to_string).collect::<Vec<String>>().join(", "));
+    Ok(())
+}
+
+/// Runs `commands` through `model` and checks that they end up at the target.
+fn verify<M : MovementModel>(model : M, commands : &[MovementCommand], horizontal : i64, depth : i64) -> Result<bool, SurfaceBreach> {
+    let mut submarine = Submarine::new(model);
+    submarine.r#move(commands)?;
+    let point = submarine.state.point();
+    Ok(point.horizontal == horizontal && point.depth == depth)
+}
+
+/// What happens when a command would take the submarine above the surface,
+/// i.e. to a negative depth.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+enum SurfacePolicy {
+    /// Stop before the command and report it.
+    #[default]
+    Error,
+    /// Stop at the surface and keep going.
+    ClampToSurface,
+    /// Let the submarine fly.
+    AllowFlying,
+}
+
+#[derive(Error, Debug, PartialEq)]
+#[error("command {index} ({command}) would take the submarine to depth {depth}")]
+struct SurfaceBreach {
+    index : usize,
+    command : MovementCommand,
+    depth : i64,
+}
+
+impl SurfacePolicy {
+    /// Checks the depth reached by the command at `index`, coming from the
+    /// `previous` depth. Breaches are collected in `breaches` unless the policy
+    /// turns them into an error. A flying submarine only breaches the surface
+    /// again once it has come back down.
+    fn check(&self, index : usize, command : &MovementCommand, previous : i64, depth : &mut i64, breaches : &mut Vec<SurfaceBreach>) -> Result<(), SurfaceBreach> {
+        if *depth >= 0 {
+            return Ok(());
+        }
+        let breach = SurfaceBreach {
+            index,
+            command: command.clone(),
+            depth: *depth,
+        };
+        match self {
+            SurfacePolicy::Error => return Err(breach),
+            SurfacePolicy::ClampToSurface => *depth = 0,
+            SurfacePolicy::AllowFlying if previous < 0 => return Ok(()),
+            SurfacePolicy::AllowFlying => {},
+        }
+        breaches.push(breach);
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+enum RunError {
+    #[error(transparent)]
+    Script(#[from] ScriptError),
+    #[error(transparent)]
+    Breach(#[from] SurfaceBreach),
+}
+
+struct Submarine<M : MovementModel> {
+    model : M,
+    state : M::State,
+}
+
+impl<M : MovementModel> Submarine<M> {
+    fn new(model : M) -> Self {
+        Submarine {
+            model,
+            state: M::State::default(),
+        }
+    }
+
+    fn r#move(&mut self, commands : &[MovementCommand]) -> Result<Vec<SurfaceBreach>, SurfaceBreach> {
+        self.move_with(commands, SurfacePolicy::default())
+    }
+
+    fn move_with(&mut self, commands : &[MovementCommand], policy : SurfacePolicy) -> Result<Vec<SurfaceBreach>, SurfaceBreach> {
+        let mut breaches = vec![];
+        for (index, command) in commands.iter().enumerate() {
+            self.step(index, command, policy, &mut breaches)?;
+        }
+        Ok(breaches)
+    }
+
+    fn run(&mut self, script : &Script, policy : SurfacePolicy) -> Result<Vec<SurfaceBreach>, RunError> {
+        let mut breaches = vec![];
+        let mut index = 0;
+        script.execute(&mut |command| {
+            self.step(index, command, policy, &mut breaches)?;
+            index += 1;
+            Ok::<(), RunError>(())
+        })?;
+        Ok(breaches)
+    }
+
+    fn record(&mut self, commands : &[MovementCommand], policy : SurfacePolicy) -> Result<Trajectory, SurfaceBreach> {
+        let mut trajectory = Trajectory::new(self.state.point());
+        let mut breaches = vec![];
+        for (index, command) in commands.iter().enumerate() {
+            self.step(index, command, policy, &mut breaches)?;
+            trajectory.points.push(self.state.point());
+        }
+        Ok(trajectory)
+    }
+
+    fn step(&mut self, index : usize, command : &MovementCommand, policy : SurfacePolicy, breaches : &mut Vec<SurfaceBreach>) -> Result<(), SurfaceBreach> {
+        let previous = self.state.point().depth;
+        let mut next = self.state;
+        self.model.apply(&mut next, command);
+        policy.check(index, command, previous, next.depth_mut(), breaches)?;
+        self.state = next;
+        Ok(())
+    }
+
+    fn result(&self) -> i64 {
+        let point = self.state.point();
+        point.horizontal * point.depth
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct TrajectoryPoint {
+    horizontal : i64,
+    depth : i64,
+    aim : Option<i64>,
+}
+
+/// Every position the submarine passed through. The first point is where it
+/// started and point `n` is where it was after `n` commands.
+#[derive(Debug, PartialEq)]
+struct Trajectory {
+    points : Vec<TrajectoryPoint>,
+}
+
+impl Trajectory {
+    fn new(start : TrajectoryPoint) -> Self {
+        Trajectory {
+            points: vec![start],
+        }
+    }
+
+    fn max_depth(&self) -> i64 {
+        self.points.iter()
+            .map(|point| point.depth)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Length of the path, following each command in a straight line.
+    fn distance_travelled(&self) -> f64 {
+        self.points.windows(2)
+            .map(|pair| {
+                let horizontal = (pair[1].horizontal - pair[0].horizontal) as f64;
+                let depth = (pair[1].depth - pair[0].depth) as f64;
+                horizontal.hypot(depth)
+            })
+            .sum()
+    }
+
+    /// Number of commands after which the submarine first got to `depth` or
+    /// deeper.
+    fn step_reaching_depth(&self, depth : i64) -> Option<usize> {
+        self.points.iter().position(|point| point.depth >= depth)
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = "step,horizontal,depth,aim\n".to_string();
+        for (step, point) in self.points.iter().enumerate() {
+            let aim = point.aim.map(|aim| aim.to_string()).unwrap_or_default();
+            csv.push_str(&format!("{},{},{},{}\n", step, point.horizontal, point.depth, aim));
+        }
+        csv
+    }
+
+    /// The path as an SVG polyline, with depth increasing down the page.
+    fn to_svg(&self) -> String {
+        let min_x = self.points.iter().map(|point| point.horizontal).min().unwrap_or_default();
+        let max_x = self.points.iter().map(|point| point.horizontal).max().unwrap_or_default();
+        let min_y = self.points.iter().map(|point| point.depth).min().unwrap_or_default();
+        let max_y = self.points.iter().map(|point| point.depth).max().unwrap_or_default();
+        let points = self.points.iter()
+            .map(|point| format!("{},{}", point.horizontal, point.depth))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\"><polyline fill=\"none\" stroke=\"black\" vector-effect=\"non-scaling-stroke\" points=\"{}\"/></svg>",
+            min_x, min_y, (max_x - min_x).max(1), (max_y - min_y).max(1), points,
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum MovementCommand {
+    Forward(usize),
+    Down(usize),
+    Up(usize),
+    Back(usize),
+    Surface,
+}
+
+impl std::fmt::Display for MovementCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovementCommand::Forward(count) => write!(f, "forward {}", count),
+            MovementCommand::Down(count) => write!(f, "down {}", count),
+            MovementCommand::Up(count) => write!(f, "up {}", count),
+            MovementCommand::Back(count) => write!(f, "back {}", count),
+            MovementCommand::Surface => write!(f, "surface"),
         }
     }
 }
 
+#[derive(Error, Debug, PartialEq, Clone)]
+enum ScriptError {
+    #[error("line {line}: unknown command {command}")]
+    UnknownCommand { line : usize, command : String },
+    #[error("line {line}: {command} expects a count")]
+    MissingCount { line : usize, command : String },
+    #[error("line {line}: invalid count {value}")]
+    InvalidCount { line : usize, value : String },
+    #[error("line {line}: unexpected {token}")]
+    UnexpectedToken { line : usize, token : String },
+    #[error("line {line}: expected {{")]
+    ExpectedBlock { line : usize },
+    #[error("line {line}: block is never closed")]
+    UnclosedBlock { line : usize },
+    #[error("line {line}: macros can only be defined at the top level")]
+    NestedMacro { line : usize },
+    #[error("line {line}: {name} is a built-in command and can't be a macro")]
+    ReservedMacroName { line : usize, name : String },
+    #[error("line {line}: macro {name} is already defined")]
+    DuplicateMacro { line : usize, name : String },
+    #[error("line {line}: macro {name} is not defined")]
+    UndefinedMacro { line : usize, name : String },
+}
+
+impl From<ScriptError> for InputFileError {
+    fn from(error : ScriptError) -> Self {
+        InputFileError::ParseError(Box::new(error))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Statement {
+    Command(MovementCommand),
+    Repeat(usize, Vec<Statement>),
+    Call { line : usize, name : String },
+}
+
+/// A parsed submarine script. Besides the plain movement commands it supports
+/// `#` comments, `repeat N { ... }` blocks and named macros declared with
+/// `macro name { ... }` and invoked by writing their name as a command. Macros
+/// have to be declared before they are used, which also rules out recursion.
+#[derive(Debug, PartialEq, Default)]
+struct Script {
+    macros : HashMap<String, Vec<Statement>>,
+    statements : Vec<Statement>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Token<'a> {
+    line : usize,
+    value : &'a str,
+}
+
+fn tokenize(s : &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    for (i, line) in s.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        for word in code.split_whitespace() {
+            let mut rest = word;
+            while !rest.is_empty() {
+                let end = match rest.find(['{', '}']) {
+                    Some(0) => 1,
+                    Some(brace) => brace,
+                    None => rest.len(),
+                };
+                tokens.push(Token {
+                    line: i + 1,
+                    value: &rest[..end],
+                });
+                rest = &rest[end..];
+            }
+        }
+    }
+    tokens
+}
+
+/// Words that always mean a built-in statement, so no macro can use them.
+const BUILT_INS : [&str; 7] = ["forward", "down", "up", "back", "surface", "repeat", "macro"];
+
+struct Parser<'a> {
+    tokens : Vec<Token<'a>>,
+    position : usize,
+    macros : HashMap<String, Vec<Statement>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&Token<'a>> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn count(&mut self, line : usize, command : &str) -> Result<usize, ScriptError> {
+        match self.peek() {
+            Some(token) if token.line == line => {
+                let value = token.value;
+                self.position += 1;
+                value.parse().map_err(|_| ScriptError::InvalidCount {
+                    line,
+                    value: value.to_string(),
+                })
+            },
+            _ => Err(ScriptError::MissingCount {
+                line,
+                command: command.to_string(),
+            }),
+        }
+    }
+
+    fn block(&mut self, line : usize) -> Result<Vec<Statement>, ScriptError> {
+        match self.next() {
+            Some(Token { value: "{", .. }) => {},
+            _ => return Err(ScriptError::ExpectedBlock { line }),
+        }
+        let mut statements = vec![];
+        loop {
+            match self.peek() {
+                Some(Token { value: "}", .. }) => {
+                    self.position += 1;
+                    return Ok(statements);
+                },
+                Some(_) => statements.extend(self.statement(false)?),
+                None => return Err(ScriptError::UnclosedBlock { line }),
+            }
+        }
+    }
+
+    /// Parses the next statement. Macro declarations don't produce a statement
+    /// of their own, they are only recorded for later calls.
+    fn statement(&mut self, top_level : bool) -> Result<Option<Statement>, ScriptError> {
+        let (line, value) = match self.next() {
+            Some(token) => (token.line, token.value),
+            None => return Ok(None),
+        };
+        let statement = match value {
+            "forward" => Statement::Command(MovementCommand::Forward(self.count(line, value)?)),
+            "down" => Statement::Command(MovementCommand::Down(self.count(line, value)?)),
+            "up" => Statement::Command(MovementCommand::Up(self.count(line, value)?)),
+            "back" => Statement::Command(MovementCommand::Back(self.count(line, value)?)),
+            "surface" => Statement::Command(MovementCommand::Surface),
+            "repeat" => {
+                let count = self.count(line, value)?;
+                Statement::Repeat(count, self.block(line)?)
+            },
+            "macro" => {
+                if !top_level {
+                    return Err(ScriptError::NestedMacro { line });
+                }
+                let name = match self.next() {
+                    Some(token) if token.line == line && token.value != "{" => token.value.to_string(),
+                    _ => return Err(ScriptError::UnexpectedToken {
+                        line,
+                        token: "macro without a name".to_string(),
+                    }),
+                };
+                if BUILT_INS.contains(&name.as_str()) {
+                    return Err(ScriptError::ReservedMacroName {
+                        line,
+                        name,
+                    });
+                }
+                if self.macros.contains_key(&name) {
+                    return Err(ScriptError::DuplicateMacro {
+                        line,
+                        name,
+                    });
+                }
+                let body = self.block(line)?;
+                self.macros.insert(name, body);
+                return Ok(None);
+            },
+            "{" | "}" => return Err(ScriptError::UnexpectedToken {
+                line,
+                token: value.to_string(),
+            }),
+            name if self.macros.contains_key(name) => Statement::Call {
+                line,
+                name: name.to_string(),
+            },
+            command => return Err(ScriptError::UnknownCommand {
+                line,
+                command: command.to_string(),
+            }),
+        };
+        Ok(Some(statement))
+    }
+}
+
+impl Script {
+    /// Runs every command of the script through `f` in order, stopping at the
+    /// first error.
+    fn execute<E : From<ScriptError>, F : FnMut(&MovementCommand) -> Result<(), E>>(&self, f : &mut F) -> Result<(), E> {
+        self.execute_statements(&self.statements, f)
+    }
+
+    fn execute_statements<E : From<ScriptError>, F : FnMut(&MovementCommand) -> Result<(), E>>(&self, statements : &[Statement], f : &mut F) -> Result<(), E> {
+        for statement in statements.iter() {
+            match statement {
+                Statement::Command(command) => f(command)?,
+                Statement::Repeat(count, body) => {
+                    for _ in 0..*count {
+                        self.execute_statements(body, f)?;
+                    }
+                },
+                Statement::Call { line, name } => match self.macros.get(name) {
+                    Some(body) => self.execute_statements(body, f)?,
+                    None => return Err(ScriptError::UndefinedMacro {
+                        line: *line,
+                        name: name.clone(),
+                    }.into()),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn commands(&self) -> Result<Vec<MovementCommand>, ScriptError> {
+        let mut commands = vec![];
+        self.execute(&mut |command| {
+            commands.push(command.clone());
+            Ok::<(), ScriptError>(())
+        })?;
+        Ok(commands)
+    }
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            position: 0,
+            macros: HashMap::new(),
+        };
+        let mut statements = vec![];
+        while parser.peek().is_some() {
+            statements.extend(parser.statement(true)?);
+        }
+        Ok(Script {
+            macros: parser.macros,
+            statements,
+        })
+    }
+}
+
 impl FromStr for MovementCommand {
-    type Err = InputFileError;
+    type Err = ScriptError;
 
     fn from_str(s : &str) -> Result<Self, Self::Err> {
-        let tokens : Vec<&str> = s.split(" ").collect();
-
-        if tokens.len() == 2 {
-            let count : usize = tokens[1].parse()?;
-            match tokens[0] {
-                "forward" => Ok(MovementCommand::Forward(count)),
-                "up" => Ok(MovementCommand::Up(count)),
-                "down" => Ok(MovementCommand::Down(count)),
-                _ => Err(InputFileError::GeneralError(format!("unknown command {}", tokens[0])))
-            }
-        } else {
-            Err(InputFileError::GeneralError(format!("unable to parse {}", s)))
+        let script : Script = FromStr::from_str(s)?;
+        match script.statements.as_slice() {
+            [Statement::Command(command)] => Ok(command.clone()),
+            _ => Err(ScriptError::UnexpectedToken {
+                line: 1,
+                token: s.to_string(),
+            }),
         }
     }
 }
@@ -127,7 +748,7 @@ mod test {
     }
 
     #[test]
-    fn move_commands() {
+    fn move_commands() -> anyhow::Result<()> {
         let commands = vec![
             Forward(5),
             Down(5),
@@ -136,18 +757,19 @@ mod test {
             Down(8),
             Forward(2),
         ];
-        let mut pos = Position::default();
-        pos.r#move(&commands);
+        let mut pos = Submarine::new(Part1Movement);
+        pos.r#move(&commands)?;
 
-        assert_eq!(pos, Position {
+        assert_eq!(pos.state, Position {
             depth: 10,
             horizontal: 15,
         });
         assert_eq!(pos.result(), 150);
+        Ok(())
     }
 
     #[test]
-    fn move_commands_part2() {
+    fn move_commands_part2() -> anyhow::Result<()> {
         let commands = vec![
             Forward(5),
             Down(5),
@@ -156,11 +778,380 @@ mod test {
             Down(8),
             Forward(2),
         ];
-        let mut pos = PositionPart2::default();
-        pos.r#move(&commands);
+        let mut pos = Submarine::new(Part2Movement);
+        pos.r#move(&commands)?;
+
+        assert_eq!(pos.state.depth, 60);
+        assert_eq!(pos.state.horizontal, 15);
+        assert_eq!(pos.result(), 900);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_script() -> anyhow::Result<()> {
+        let script : Script = FromStr::from_str("
+            # dive and come back up
+            macro dive {
+                down 2
+                forward 1
+            }
+            repeat 2 { dive }
+            back 3 # not quite as far
+            surface
+        ")?;
+
+        assert_eq!(script.statements, vec![
+            Statement::Repeat(2, vec![Statement::Call { line: 7, name: "dive".to_string() }]),
+            Statement::Command(Back(3)),
+            Statement::Command(Surface),
+        ]);
+        assert_eq!(script.commands()?, vec![
+            Down(2),
+            Forward(1),
+            Down(2),
+            Forward(1),
+            Back(3),
+            Surface,
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn undefined_macro() {
+        let script = Script {
+            macros: HashMap::new(),
+            statements: vec![
+                Statement::Command(Down(1)),
+                Statement::Call { line: 2, name: "dive".to_string() },
+            ],
+        };
+
+        let error = ScriptError::UndefinedMacro { line: 2, name: "dive".to_string() };
+        assert_eq!(script.commands(), Err(error.clone()));
+        assert_eq!(Submarine::new(Part1Movement).run(&script, SurfacePolicy::Error), Err(RunError::Script(error)));
+    }
 
-        assert_eq!(pos.depth, 60);
-        assert_eq!(pos.horizontal, 15);
+    #[test]
+    fn run_script() -> anyhow::Result<()> {
+        let script : Script = FromStr::from_str("
+            repeat 3 {
+                forward 4
+                down 2
+            }
+            back 2
+        ")?;
+
+        let mut pos = Submarine::new(Part1Movement);
+        pos.run(&script, SurfacePolicy::Error)?;
+        assert_eq!(pos.state, Position {
+            horizontal: 10,
+            depth: 6,
+        });
+
+        let mut pos = Submarine::new(Part2Movement);
+        pos.run(&script, SurfacePolicy::Error)?;
+        assert_eq!(pos.state, PositionPart2 {
+            horizontal: 10,
+            depth: 12,
+            aim: 6,
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn run_sample_script() -> anyhow::Result<()> {
+        let script : Script = aoc2021::load_sample_text(2)?;
+
+        let mut pos = Submarine::new(Part1Movement);
+        pos.run(&script, SurfacePolicy::Error)?;
+        assert_eq!(pos.result(), 150);
+
+        let mut pos = Submarine::new(Part2Movement);
+        pos.run(&script, SurfacePolicy::Error)?;
         assert_eq!(pos.result(), 900);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("forward 5\nsideways 3", ScriptError::UnknownCommand { line: 2, command: "sideways".to_string() })]
+    #[case("forward\n5", ScriptError::MissingCount { line: 1, command: "forward".to_string() })]
+    #[case("down five", ScriptError::InvalidCount { line: 1, value: "five".to_string() })]
+    #[case("dive\nmacro dive { down 1 }", ScriptError::UnknownCommand { line: 1, command: "dive".to_string() })]
+    #[case("repeat 2 forward 1", ScriptError::ExpectedBlock { line: 1 })]
+    #[case("up 1\nrepeat 2 {\nforward 1", ScriptError::UnclosedBlock { line: 2 })]
+    #[case("forward 1 }", ScriptError::UnexpectedToken { line: 1, token: "}".to_string() })]
+    #[case("repeat 2 {\nmacro dive { down 1 }\n}", ScriptError::NestedMacro { line: 2 })]
+    #[case("macro forward { down 1 }", ScriptError::ReservedMacroName { line: 1, name: "forward".to_string() })]
+    #[case("macro repeat { down 1 }", ScriptError::ReservedMacroName { line: 1, name: "repeat".to_string() })]
+    #[case("macro dive { down 1 }\n\n# again\nmacro dive { down 2 }", ScriptError::DuplicateMacro { line: 4, name: "dive".to_string() })]
+    #[case("forward 1\n\n\nsideways 3", ScriptError::UnknownCommand { line: 4, command: "sideways".to_string() })]
+    fn script_errors(#[case] s : &str, #[case] error : ScriptError) {
+        let parsed : Result<Script, ScriptError> = FromStr::from_str(s);
+        assert_eq!(parsed, Err(error.clone()));
+
+        // the error keeps its type through `InputFileError`
+        match InputFileError::from(error.clone()) {
+            InputFileError::ParseError(parse_error) => assert_eq!(parse_error.downcast_ref::<ScriptError>(), Some(&error)),
+            other => panic!("expected a script error, got {:?}", other),
+        }
+    }
+
+    #[rstest]
+    #[case(SurfacePolicy::Error, Position { horizontal: 5, depth: 2 }, Err(SurfaceBreach { index: 2, command: Up(3), depth: -1 }))]
+    #[case(SurfacePolicy::ClampToSurface, Position { horizontal: 5, depth: 1 }, Ok(vec![SurfaceBreach { index: 2, command: Up(3), depth: -1 }]))]
+    #[case(SurfacePolicy::AllowFlying, Position { horizontal: 5, depth: 0 }, Ok(vec![SurfaceBreach { index: 2, command: Up(3), depth: -1 }]))]
+    fn surface_policy(#[case] policy : SurfacePolicy, #[case] expected : Position, #[case] breaches : Result<Vec<SurfaceBreach>, SurfaceBreach>) {
+        let commands = vec![
+            Forward(5),
+            Down(2),
+            Up(3),
+            Down(1),
+        ];
+        let mut pos = Submarine::new(Part1Movement);
+
+        assert_eq!(pos.move_with(&commands, policy), breaches);
+        assert_eq!(pos.state, expected);
+    }
+
+    #[rstest]
+    #[case(SurfacePolicy::ClampToSurface, vec![0, 1])]
+    #[case(SurfacePolicy::AllowFlying, vec![0, 3])]
+    fn repeated_breaches(#[case] policy : SurfacePolicy, #[case] indices : Vec<usize>) -> anyhow::Result<()> {
+        let commands = vec![
+            Up(1),
+            Up(1),
+            Down(3),
+            Up(2),
+        ];
+        let breaches = Submarine::new(Part1Movement).move_with(&commands, policy)?;
+
+        assert_eq!(breaches.iter().map(|breach| breach.index).collect::<Vec<usize>>(), indices);
+        Ok(())
+    }
+
+    #[test]
+    fn surface_policy_part2() {
+        let commands = vec![
+            Down(1),
+            Forward(2),
+            Up(3),
+            Forward(1),
+            Forward(1),
+        ];
+
+        let mut pos = Submarine::new(Part2Movement);
+        assert_eq!(pos.r#move(&commands), Err(SurfaceBreach { index: 4, command: Forward(1), depth: -2 }));
+        assert_eq!(pos.state, PositionPart2 { horizontal: 3, depth: 0, aim: -2 });
+
+        let mut pos = Submarine::new(Part2Movement);
+        assert_eq!(pos.move_with(&commands, SurfacePolicy::AllowFlying).map(|breaches| breaches.len()), Ok(1));
+        assert_eq!(pos.state, PositionPart2 { horizontal: 4, depth: -2, aim: -2 });
+    }
+
+    #[test]
+    fn surface_breach_in_script() -> anyhow::Result<()> {
+        let script : Script = FromStr::from_str("
+            down 1
+            repeat 3 { up 1 }
+        ")?;
+
+        let mut pos = Submarine::new(Part1Movement);
+        let breach = match pos.run(&script, SurfacePolicy::Error) {
+            Err(RunError::Breach(breach)) => breach,
+            result => return Err(anyhow::anyhow!("expected a breach, got {:?}", result)),
+        };
+        assert_eq!(breach.index, 2);
+        assert_eq!(breach.to_string(), "command 2 (up 1) would take the submarine to depth -1");
+        Ok(())
+    }
+
+    #[test]
+    fn record_trajectory() -> anyhow::Result<()> {
+        let commands = vec![
+            Forward(5),
+            Down(5),
+            Forward(8),
+            Up(3),
+            Down(8),
+            Forward(2),
+        ];
+
+        let trajectory = Submarine::new(Part1Movement).record(&commands, SurfacePolicy::Error)?;
+        assert_eq!(trajectory.points.len(), 7);
+        assert_eq!(trajectory.points[6], TrajectoryPoint { horizontal: 15, depth: 10, aim: None });
+        assert_eq!(trajectory.max_depth(), 10);
+        assert_eq!(trajectory.distance_travelled(), 31.0);
+        assert_eq!(trajectory.step_reaching_depth(5), Some(2));
+        assert_eq!(trajectory.step_reaching_depth(11), None);
+
+        let trajectory = Submarine::new(Part2Movement).record(&commands, SurfacePolicy::Error)?;
+        assert_eq!(trajectory.points[3], TrajectoryPoint { horizontal: 13, depth: 40, aim: Some(5) });
+        assert_eq!(trajectory.max_depth(), 60);
+        assert_eq!(trajectory.step_reaching_depth(41), Some(6));
+        Ok(())
+    }
+
+    #[test]
+    fn export_trajectory() -> anyhow::Result<()> {
+        let commands = vec![
+            Forward(3),
+            Down(4),
+            Forward(1),
+        ];
+
+        let trajectory = Submarine::new(Part2Movement).record(&commands, SurfacePolicy::Error)?;
+        assert_eq!(trajectory.to_csv(), "step,horizontal,depth,aim\n0,0,0,0\n1,3,0,0\n2,3,0,4\n3,4,4,4\n");
+        assert_eq!(trajectory.distance_travelled(), 3.0 + 17.0_f64.sqrt());
+
+        let trajectory = Submarine::new(Part1Movement).record(&commands, SurfacePolicy::Error)?;
+        assert_eq!(trajectory.to_csv(), "step,horizontal,depth,aim\n0,0,0,\n1,3,0,\n2,3,4,\n3,4,4,\n");
+        assert_eq!(trajectory.to_svg(), concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 4 4\">",
+            "<polyline fill=\"none\" stroke=\"black\" vector-effect=\"non-scaling-stroke\" points=\"0,0 3,0 3,4 4,4\"/></svg>",
+        ));
+        Ok(())
+    }
+
+    /// Moves along a heading instead of up and down, to check that the
+    /// submarine driver works with models other than the puzzle ones.
+    struct Heading;
+
+    #[derive(Default, PartialEq, Debug, Clone, Copy)]
+    struct Heading3d {
+        x : i64,
+        y : i64,
+        depth : i64,
+        heading : usize,
+    }
+
+    impl SubmarineState for Heading3d {
+        fn depth_mut(&mut self) -> &mut i64 {
+            &mut self.depth
+        }
+
+        fn point(&self) -> TrajectoryPoint {
+            TrajectoryPoint {
+                horizontal: self.x,
+                depth: self.depth,
+                aim: None,
+            }
+        }
+    }
+
+    impl MovementModel for Heading {
+        type State = Heading3d;
+
+        fn apply(&self, state : &mut Heading3d, command : &MovementCommand) {
+            let (dx, dy) = [(1, 0), (0, 1), (-1, 0), (0, -1)][state.heading];
+            match command {
+                Forward(count) => {
+                    state.x += dx * *count as i64;
+                    state.y += dy * *count as i64;
+                },
+                Back(count) => {
+                    state.x -= dx * *count as i64;
+                    state.y -= dy * *count as i64;
+                },
+                Down(turns) => state.heading = (state.heading + turns) % 4,
+                Up(count) => state.depth -= *count as i64,
+                Surface => state.depth = 0,
+            }
+        }
+    }
+
+    #[test]
+    fn custom_movement_model() -> anyhow::Result<()> {
+        let commands = vec![
+            Forward(5),
+            Down(1),
+            Forward(3),
+            Down(3),
+            Back(2),
+            Up(1),
+        ];
+
+        let mut submarine = Submarine::new(Heading);
+        assert_eq!(submarine.move_with(&commands, SurfacePolicy::ClampToSurface)?.len(), 1);
+        assert_eq!(submarine.state, Heading3d { x: 3, y: 3, depth: 0, heading: 0 });
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(0, 0, vec![])]
+    #[case(15, 10, vec![Forward(15), Down(10)])]
+    #[case(-3, 0, vec![Back(3)])]
+    #[case(0, 4, vec![Down(4)])]
+    fn solve_part1(#[case] horizontal : i64, #[case] depth : i64, #[case] expected : Vec<MovementCommand>) -> anyhow::Result<()> {
+        let commands = Part1Movement.solve(horizontal, depth)?;
+
+        assert_eq!(commands, expected);
+        assert!(verify(Part1Movement, &commands, horizontal, depth)?);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(0, 0, vec![])]
+    #[case(15, 0, vec![Forward(15)])]
+    #[case(15, 60, vec![Down(4), Forward(15)])]
+    #[case(-4, 8, vec![Up(2), Back(4)])]
+    #[case(15, 61, vec![Forward(14), Down(61), Forward(1)])]
+    #[case(-4, 7, vec![Back(3), Up(7), Back(1)])]
+    #[case(0, 5, vec![Forward(1), Up(5), Back(1)])]
+    fn solve_part2(#[case] horizontal : i64, #[case] depth : i64, #[case] expected : Vec<MovementCommand>) -> anyhow::Result<()> {
+        let commands = Part2Movement.solve(horizontal, depth)?;
+
+        assert_eq!(commands, expected);
+        assert!(verify(Part2Movement, &commands, horizontal, depth)?);
+        Ok(())
+    }
+
+    #[test]
+    fn solve_above_surface() {
+        assert_eq!(Part1Movement.solve(1, -1), Err(SolveError::AboveSurface(-1)));
+        assert_eq!(Part2Movement.solve(1, -1), Err(SolveError::AboveSurface(-1)));
+    }
+
+    /// Every command sequence of up to `length` commands with counts up to 10.
+    fn sequences(length : usize) -> Vec<Vec<MovementCommand>> {
+        let mut commands = vec![Surface];
+        for count in 1..=10 {
+            commands.extend(vec![Forward(count), Down(count), Up(count), Back(count)]);
+        }
+        let mut sequences = vec![vec![]];
+        let mut current = vec![vec![]];
+        for _ in 0..length {
+            current = current.iter()
+                .flat_map(|sequence : &Vec<MovementCommand>| commands.iter().map(move |command| {
+                    let mut next = sequence.clone();
+                    next.push(command.clone());
+                    next
+                }))
+                .collect();
+            sequences.extend(current.clone());
+        }
+        sequences
+    }
+
+    #[test]
+    fn solutions_are_shortest() -> anyhow::Result<()> {
+        let shorter = sequences(2);
+        for horizontal in -4..=4 {
+            for depth in 0..=8 {
+                let part1 = Part1Movement.solve(horizontal, depth)?;
+                let part2 = Part2Movement.solve(horizontal, depth)?;
+                assert!(verify(Part1Movement, &part1, horizontal, depth)?);
+                assert!(verify(Part2Movement, &part2, horizontal, depth)?);
+
+                for sequence in shorter.iter() {
+                    if sequence.len() < part1.len() {
+                        assert!(!verify(Part1Movement, sequence, horizontal, depth).unwrap_or(false), "{:?} beats {:?}", sequence, part1);
+                    }
+                    if sequence.len() < part2.len() {
+                        assert!(!verify(Part2Movement, sequence, horizontal, depth).unwrap_or(false), "{:?} beats {:?}", sequence, part2);
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 }
@@ -1,303 +1,983 @@
-use aoc2021::input::{InputFileError, InputFile, load_input};
+use aoc2021::input::{InputFileError, load_input_text};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::rc::Rc;
 use std::str::FromStr;
-use itertools::Itertools;
+use thiserror::Error;
 
 fn main() -> anyhow::Result<()> {
-    let data: DiagnosticReport = load_input(3)?;
+    let mut radix = 2;
+    let mut stats = false;
+    let mut options = ValidationOptions::default();
+    let mut configs = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.strip_prefix("--radix=") {
+            Some(value) => radix = value.parse()?,
+            None if arg == "--stats" => stats = true,
+            None if arg == "--reject-duplicates" => options.reject_duplicates = true,
+            None => configs.push(arg),
+        }
+    }
+
+    let input : String = load_input_text(3)?;
+    let (data, duplicates) = DiagnosticReport::parse(&input, radix, options)?;
 
     println!("part1: {}", data.result()?);
 
     println!("part2: {}", data.life_support_rating()?);
 
+    for duplicate in duplicates {
+        println!("{}", duplicate);
+    }
+
+    if stats {
+        print!("{}", data.stats(&RatingConfig::default())?);
+    }
+
+    for path in configs {
+        let config : RatingConfig = FromStr::from_str(&std::fs::read_to_string(path)?)?;
+        for (name, rating) in data.ratings(&config)? {
+            println!("{}: {}", name, rating);
+        }
+    }
+
     Ok(())
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-enum BinaryDigit {
-    Zero,
-    One,
-}
+/// A single digit of a reading, in the radix of its report.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+struct Digit(u32);
 
-impl From<BinaryDigit> for usize {
-    fn from(binary_digit : BinaryDigit) -> Self {
-        match binary_digit {
-            BinaryDigit::One => 1,
-            BinaryDigit::Zero => 0,
-        }
+impl From<Digit> for usize {
+    fn from(digit : Digit) -> Self {
+        digit.0 as usize
     }
 }
 
+/// A rate or rating too large for the integer it ends up in.
+#[derive(Error, Debug, PartialEq)]
+#[error("number is too large")]
+struct Overflow;
+
 #[derive(Debug)]
-struct BinaryNumber {
-    bits : Vec<BinaryDigit>
+struct Number {
+    value : u128,
+    radix : u32,
 }
 
-impl BinaryNumber {
-    fn new() -> Self {
-        BinaryNumber {
-            bits: vec![],
+impl Number {
+    fn new(radix : u32) -> Self {
+        Number {
+            value: 0,
+            radix,
         }
     }
 
-    fn push(&mut self, digit : BinaryDigit) {
-        self.bits.push(digit);
+    fn push(&mut self, digit : Digit) -> Result<(), Overflow> {
+        self.value = self.value.checked_mul(self.radix as u128)
+            .and_then(|value| value.checked_add(digit.0 as u128))
+            .ok_or(Overflow)?;
+        Ok(())
     }
 }
 
-impl From<BinaryNumber> for usize {
-    fn from(binary_number : BinaryNumber) -> Self {
-        let mut result = 0;
-        for i in 0..binary_number.bits.len() {
-            let digit : usize = binary_number.bits[binary_number.bits.len() - i - 1].into();
-            let offset = digit * 2_usize.pow(i.try_into().unwrap());
-            result += offset;
-        }
-        result
+impl TryFrom<Number> for usize {
+    type Error = Overflow;
+
+    fn try_from(number : Number) -> Result<Self, Self::Error> {
+        usize::try_from(number.value).map_err(|_| Overflow)
     }
 }
 
-impl From<DiagnosticReading> for usize {
-    fn from(diagnostic_reading: DiagnosticReading) -> Self {
-        let mut result = 0;
-        for i in 0..diagnostic_reading.bits.len() {
-            let digit : usize = diagnostic_reading.bits[diagnostic_reading.bits.len() - i - 1].into();
-            let offset = digit * 2_usize.pow(i.try_into().unwrap());
-            result += offset;
+impl TryFrom<DiagnosticReading> for usize {
+    type Error = Overflow;
+
+    fn try_from(diagnostic_reading : DiagnosticReading) -> Result<Self, Self::Error> {
+        usize::try_from(diagnostic_reading.value()).map_err(|_| Overflow)
+    }
+}
+
+/// A growable vector of bits packed into `u64` words. Bits past `len` in the
+/// last word are always zero, so whole words can be popcounted.
+#[derive(Debug, Default, PartialEq, Clone)]
+struct BitVector {
+    words : Vec<u64>,
+    len : usize,
+}
+
+impl BitVector {
+    fn filled(len : usize) -> Self {
+        let mut words = vec![u64::MAX; len.div_ceil(64)];
+        if !len.is_multiple_of(64) {
+            if let Some(last) = words.last_mut() {
+                *last = (1 << (len % 64)) - 1;
+            }
+        }
+        BitVector {
+            words,
+            len,
+        }
+    }
+
+    fn push(&mut self, bit : bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
         }
-        result
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    #[cfg(test)]
+    fn get(&self, i : usize) -> bool {
+        i < self.len && self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn count_ones(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn count_ones_and(&self, other : &BitVector) -> usize {
+        self.words.iter()
+            .zip(other.words.iter())
+            .map(|(a, b)| (a & b).count_ones() as usize)
+            .sum()
+    }
+
+    fn and(&self, other : &BitVector) -> BitVector {
+        BitVector {
+            words: self.words.iter().zip(other.words.iter()).map(|(a, b)| a & b).collect(),
+            len: self.len,
+        }
+    }
+
+    #[cfg(test)]
+    fn and_not(&self, other : &BitVector) -> BitVector {
+        BitVector {
+            words: self.words.iter().zip(other.words.iter()).map(|(a, b)| a & !b).collect(),
+            len: self.len,
+        }
+    }
+
+    fn first_one(&self) -> Option<usize> {
+        self.words.iter()
+            .position(|word| *word != 0)
+            .map(|i| i * 64 + self.words[i].trailing_zeros() as usize)
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+/// Readings stored column by column: `columns[i][d]` has bit `r` set when
+/// reading `r` has digit `d` in column `i`, counting columns from the most
+/// significant digit. Counting digits in a column, optionally restricted to a
+/// set of readings, is a popcount over the column's words.
+#[derive(Debug, PartialEq)]
 struct DiagnosticReport {
     readings : Vec<DiagnosticReading>,
+    radix : u32,
+    columns : Vec<Vec<BitVector>>,
 }
 
-use std::hash::Hash;
+/// How often each digit occurs in a column, indexed by digit.
+#[derive(Debug, Default, PartialEq, Clone)]
+struct ColumnCount {
+    counts : Vec<usize>,
+}
+
+impl ColumnCount {
+    fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    fn count(&self, digit : Digit) -> usize {
+        self.counts.get(usize::from(digit)).copied().unwrap_or_default()
+    }
+
+    fn present(&self) -> impl Iterator<Item = (Digit, usize)> + '_ {
+        self.counts.iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(digit, count)| (Digit(digit as u32), *count))
+    }
+
+    /// Every digit sharing the highest count.
+    fn modes(&self) -> Vec<Digit> {
+        let max = self.present().map(|(_, count)| count).max();
+        self.present().filter(|(_, count)| Some(*count) == max).map(|(digit, _)| digit).collect()
+    }
+
+    /// Every digit sharing the lowest count, among the digits that do occur.
+    fn anti_modes(&self) -> Vec<Digit> {
+        let min = self.present().map(|(_, count)| count).min();
+        self.present().filter(|(_, count)| Some(*count) == min).map(|(digit, _)| digit).collect()
+    }
 
-struct Counter {
-    map : std::collections::HashMap<BinaryDigit, usize>,
+    /// The most common digit, the lowest one on ties.
+    fn max(&self) -> Option<Digit> {
+        self.modes().first().copied()
+    }
+
+    /// The least common digit that does occur, the lowest one on ties.
+    fn min(&self) -> Option<Digit> {
+        self.anti_modes().first().copied()
+    }
 }
 
-impl Default for Counter {
-    fn default() -> Self {
-        Counter {
-            map: std::collections::HashMap::new(),
+/// What a rating does when its criterion leaves several digits tied for a
+/// column.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TiePolicy {
+    Lowest,
+    Highest,
+    /// Keep this digit, or the lowest tied digit when it isn't one of them.
+    Prefer(Digit),
+    /// Keep every remaining reading and move on to the next column.
+    Skip,
+}
+
+impl TiePolicy {
+    fn resolve(&self, tied : &[Digit]) -> Option<Digit> {
+        match self {
+            TiePolicy::Lowest => tied.first().copied(),
+            TiePolicy::Highest => tied.last().copied(),
+            TiePolicy::Prefer(digit) if tied.contains(digit) => Some(*digit),
+            TiePolicy::Prefer(_) => tied.first().copied(),
+            TiePolicy::Skip => None,
         }
     }
 }
 
-impl Counter {
-    fn push(&mut self, a : BinaryDigit) {
-        *self.map.entry(a).or_insert(0) += 1;
+type CustomCriterion = Rc<dyn Fn(usize, &ColumnCount) -> Option<Digit>>;
+
+/// Picks the digit to keep in a column from the counts of the readings that
+/// are still left.
+#[derive(Clone)]
+enum Criterion {
+    MostCommon,
+    /// Least common of the digits that still occur in the column.
+    LeastCommon,
+    /// Keeps `digit` when more than `ratio` of the readings have it, and the
+    /// most common other digit when fewer do.
+    Threshold { digit : Digit, ratio : f64 },
+    /// Called with the column index and its counts, `None` ties every digit
+    /// in the column.
+    Custom(CustomCriterion),
+}
+
+impl std::fmt::Debug for Criterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Criterion::MostCommon => write!(f, "MostCommon"),
+            Criterion::LeastCommon => write!(f, "LeastCommon"),
+            Criterion::Threshold { digit, ratio } => write!(f, "Threshold({:?}, {})", digit, ratio),
+            Criterion::Custom(_) => write!(f, "Custom"),
+        }
     }
+}
 
-    fn do_max(&self) -> Option<(&BinaryDigit, &usize)> {
-        let mut max = None;
-        for (key, value) in self.map.iter() {
-            match max {
-                Some((_, max_value)) => {
-                    if max_value < value {
-                        max = Some((key, value))
+impl Criterion {
+    fn custom<F : Fn(usize, &ColumnCount) -> Option<Digit> + 'static>(select : F) -> Self {
+        Criterion::Custom(Rc::new(select))
+    }
+
+    /// The most common digit in even columns and the least common one in odd
+    /// columns, counting from zero.
+    fn alternating() -> Self {
+        Criterion::custom(|column, count| if column % 2 == 0 {
+            count.max()
+        } else {
+            count.min()
+        })
+    }
+
+    /// The digits this criterion would keep. More than one is a tie.
+    fn select(&self, column : usize, count : &ColumnCount) -> Vec<Digit> {
+        match self {
+            Criterion::MostCommon => count.modes(),
+            Criterion::LeastCommon => count.anti_modes(),
+            Criterion::Threshold { digit, ratio } if count.total() > 0 => {
+                let share = count.count(*digit) as f64 / count.total() as f64;
+                if share > *ratio {
+                    vec![*digit]
+                } else if share < *ratio {
+                    let mut others = count.clone();
+                    if let Some(excluded) = others.counts.get_mut(usize::from(*digit)) {
+                        *excluded = 0;
                     }
-                },
-                None => {
-                    max = Some((key, value))
+                    others.modes()
+                } else {
+                    count.present().map(|(digit, _)| digit).collect()
                 }
-            }
+            },
+            Criterion::Threshold { .. } => vec![],
+            Criterion::Custom(select) => match select(column, count) {
+                Some(digit) => vec![digit],
+                None => count.present().map(|(digit, _)| digit).collect(),
+            },
         }
-        max
     }
+}
+
+#[derive(Debug, Clone)]
+struct RatingCriteria {
+    criterion : Criterion,
+    tie : TiePolicy,
+}
 
-    fn max(&self) -> Option<BinaryDigit> {
-        self.do_max().map(|x| x.0.clone())
+impl RatingCriteria {
+    fn new(criterion : Criterion, tie : TiePolicy) -> Self {
+        RatingCriteria {
+            criterion,
+            tie,
+        }
     }
 
-    fn do_min(&self) -> Option<(&BinaryDigit, &usize)> {
-        let mut min = None;
-        for (key, value) in self.map.iter() {
-            match min {
-                Some((_, min_value)) => {
-                    if min_value > value {
-                        min = Some((key, value))
-                    }
-                },
-                None => {
-                    min = Some((key, value))
-                }
+    fn oxygen_generator() -> Self {
+        RatingCriteria::new(Criterion::MostCommon, TiePolicy::Highest)
+    }
+
+    fn co2_scrubber() -> Self {
+        RatingCriteria::new(Criterion::LeastCommon, TiePolicy::Lowest)
+    }
+
+    /// The digit to keep in column `i`, or `None` to keep every reading. A
+    /// criterion selecting nothing, which only happens when no readings are
+    /// left, keeps every reading too.
+    fn select(&self, i : usize, count : &ColumnCount) -> Option<Digit> {
+        match self.criterion.select(i, count).as_slice() {
+            [digit] => Some(*digit),
+            [] => None,
+            tied => self.tie.resolve(tied),
+        }
+    }
+
+    /// Fails for digits that don't exist in `radix`.
+    fn check(&self, radix : u32) -> Result<(), RatingConfigError> {
+        let digits = [
+            match self.criterion {
+                Criterion::Threshold { digit, .. } => Some(digit),
+                _ => None,
+            },
+            match self.tie {
+                TiePolicy::Prefer(digit) => Some(digit),
+                _ => None,
+            },
+        ];
+        match digits.into_iter().flatten().find(|digit| digit.0 >= radix) {
+            Some(digit) => Err(RatingConfigError::DigitOutOfRange {
+                digit: digit.0,
+                radix,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Filters the readings column by column until a single one is left,
+    /// returning the readings left and how many survived each column.
+    fn filter(&self, report : &DiagnosticReport) -> Result<(BitVector, Vec<usize>), RatingConfigError> {
+        self.check(report.radix)?;
+        let mut remaining = BitVector::filled(report.readings.len());
+        let mut trace = vec![];
+        for i in 0..report.max_bit_width() {
+            if remaining.count_ones() == 1 {
+                break;
+            }
+            if let Some(digit) = self.select(i, &report.count(i, &remaining)) {
+                // custom criteria can pick any digit
+                let digits = report.columns[i].get(usize::from(digit)).ok_or(RatingConfigError::DigitOutOfRange {
+                    digit: digit.0,
+                    radix: report.radix,
+                })?;
+                remaining = remaining.and(digits);
             }
+            trace.push(remaining.count_ones());
         }
-        min
+        Ok((remaining, trace))
     }
 
-    fn min(&self) -> Option<BinaryDigit> {
-        self.do_min().map(|x| x.0.clone())
+    fn calculate(&self, report : &DiagnosticReport) -> anyhow::Result<usize> {
+        let (remaining, _) = self.filter(report)?;
+        match remaining.count_ones() {
+            1 => match remaining.first_one() {
+                Some(index) => Ok(usize::try_from(report.readings[index])?),
+                None => Err(anyhow::anyhow!("unable to calculate")),
+            },
+            0 => Err(anyhow::anyhow!("no readings match {:?}", self)),
+            count => Err(anyhow::anyhow!("{} readings match {:?}", count, self)),
+        }
     }
+}
 
-    fn is_equal(&self) -> bool {
-        let result : Vec<&usize> = self.map.values().unique().collect();
-        result.len() == 1
+fn parse_digit(s : &str, radix : u32) -> Option<Digit> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.to_digit(radix).map(Digit),
+        _ => None,
     }
 }
 
-struct RatingGenerator {
-    readings : Vec<DiagnosticReading>,
-    heristic : Box<dyn Fn(&Counter) -> Option<BinaryDigit>>,
-    default_when_equal : BinaryDigit,
+/// Named ratings, one per line as `name = criterion [tie=POLICY]` where the
+/// criterion is `most_common`, `least_common`, `alternating` or
+/// `threshold RATIO [DIGIT]` with a ratio from 0 to 1, and the tie policy is
+/// `lowest`, `highest`, `skip`, a digit, or `zero` and `one` for binary
+/// reports. Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone)]
+struct RatingConfig {
+    ratings : Vec<(String, RatingCriteria)>,
 }
 
-impl RatingGenerator {
-    fn new(report : &DiagnosticReport, heristic : Box<dyn Fn(&Counter) -> Option<BinaryDigit>>, default_when_equal : BinaryDigit) -> Self {
-        RatingGenerator {
-            readings: report.readings.clone(),
-            heristic,
-            default_when_equal,
+impl Default for RatingConfig {
+    fn default() -> Self {
+        RatingConfig {
+            ratings: vec![
+                ("oxygen".to_string(), RatingCriteria::oxygen_generator()),
+                ("co2".to_string(), RatingCriteria::co2_scrubber()),
+            ],
         }
     }
+}
 
-    fn find_bit(&mut self, pos : usize) -> anyhow::Result<BinaryDigit> {
-        let mut counter = Counter::default();
-        for reading in self.readings.iter() {
-            counter.push(reading.bits[pos]);
-        }
-        if counter.is_equal() {
-            Ok(self.default_when_equal)
-        } else {
-            match (self.heristic)(&counter) {
-                Some(value) => Ok(value),
-                None => Err(anyhow::anyhow!("could not find a value bit")),
-            }
-        }
+#[derive(Error, Debug, PartialEq)]
+enum RatingConfigError {
+    #[error("line {line}: expected name = criterion")]
+    MissingName { line : usize },
+    #[error("line {line}: missing criterion")]
+    MissingCriterion { line : usize },
+    #[error("line {line}: unknown criterion {criterion}")]
+    UnknownCriterion { line : usize, criterion : String },
+    #[error("line {line}: threshold expects a ratio")]
+    MissingRatio { line : usize },
+    #[error("line {line}: ratio {ratio} is not between 0 and 1")]
+    InvalidRatio { line : usize, ratio : String },
+    #[error("line {line}: invalid digit {digit}")]
+    InvalidDigit { line : usize, digit : String },
+    #[error("line {line}: unexpected tie={tie}")]
+    InvalidTie { line : usize, tie : String },
+    #[error("line {line}: unexpected {token}")]
+    UnexpectedToken { line : usize, token : String },
+    #[error("digit {digit} doesn't exist in base {radix}")]
+    DigitOutOfRange { digit : u32, radix : u32 },
+}
+
+impl From<RatingConfigError> for InputFileError {
+    fn from(error : RatingConfigError) -> Self {
+        InputFileError::ParseError(Box::new(error))
     }
+}
+
+impl FromStr for RatingConfig {
+    type Err = RatingConfigError;
 
-    fn calculate(&mut self) -> anyhow::Result<usize> {
-        let mut binary_number = BinaryNumber::new();
-        for i in 0..self.readings[0].bits.len() {
-            let digit = self.find_bit(i)?;
-            //println!("digit: {:?}", digit);
-            binary_number.push(digit);
-            //println!("current readings: {}", self.readings.len());
-            self.readings = self.readings.iter()
-                .filter(|reading| reading.bits[i] == digit)
-                .map(Clone::clone)
-                .collect();
-            //println!("after filter readings: {} -> {:?}", self.readings.len(), binary_number);
-            if self.readings.len() == 1 {
-                return Ok(self.readings[0].clone().into())
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let mut ratings = vec![];
+        for (line, text) in s.lines().enumerate().map(|(i, text)| (i + 1, text.trim())) {
+            if text.is_empty() || text.starts_with('#') {
+                continue;
             }
+            let (name, definition) = text.split_once('=')
+                .ok_or(RatingConfigError::MissingName { line })?;
+            let mut tokens = definition.split_whitespace().peekable();
+            let (criterion, default_tie) = match tokens.next() {
+                Some("most_common") => (Criterion::MostCommon, TiePolicy::Highest),
+                Some("least_common") => (Criterion::LeastCommon, TiePolicy::Lowest),
+                Some("alternating") => (Criterion::alternating(), TiePolicy::Highest),
+                Some("threshold") => {
+                    let (token, ratio) = tokens.next()
+                        .and_then(|token| Some((token, token.parse::<f64>().ok()?)))
+                        .ok_or(RatingConfigError::MissingRatio { line })?;
+                    if !(0.0..=1.0).contains(&ratio) {
+                        return Err(RatingConfigError::InvalidRatio { line, ratio: token.to_string() });
+                    }
+                    let digit = match tokens.peek() {
+                        Some(token) if !token.starts_with("tie=") => {
+                            let digit = parse_digit(token, 36)
+                                .ok_or_else(|| RatingConfigError::InvalidDigit { line, digit: token.to_string() })?;
+                            tokens.next();
+                            digit
+                        },
+                        _ => Digit(1),
+                    };
+                    (Criterion::Threshold { digit, ratio }, TiePolicy::Highest)
+                },
+                Some(criterion) => return Err(RatingConfigError::UnknownCriterion { line, criterion: criterion.to_string() }),
+                None => return Err(RatingConfigError::MissingCriterion { line }),
+            };
+            let tie = match tokens.next().map(|token| token.strip_prefix("tie=").ok_or(token)) {
+                None => default_tie,
+                Some(Ok("lowest")) => TiePolicy::Lowest,
+                Some(Ok("highest")) => TiePolicy::Highest,
+                Some(Ok("skip")) => TiePolicy::Skip,
+                Some(Ok("zero")) => TiePolicy::Prefer(Digit(0)),
+                Some(Ok("one")) => TiePolicy::Prefer(Digit(1)),
+                Some(Ok(digit)) => match parse_digit(digit, 36) {
+                    Some(digit) => TiePolicy::Prefer(digit),
+                    None => return Err(RatingConfigError::InvalidTie { line, tie: digit.to_string() }),
+                },
+                Some(Err(token)) => return Err(RatingConfigError::UnexpectedToken { line, token: token.to_string() }),
+            };
+            if let Some(token) = tokens.next() {
+                return Err(RatingConfigError::UnexpectedToken { line, token: token.to_string() });
+            }
+            ratings.push((name.trim().to_string(), RatingCriteria::new(criterion, tie)));
         }
-        Err(anyhow::anyhow!("unable to calculate"))
+        Ok(RatingConfig {
+            ratings,
+        })
     }
 }
 
 impl DiagnosticReport {
     fn result(&self) -> anyhow::Result<usize> {
-        Ok(self.gamma_rate()? * self.epsilon_rate()?)
+        Ok(self.gamma_rate()?.checked_mul(self.epsilon_rate()?).ok_or(Overflow)?)
     }
 
     fn life_support_rating(&self) -> anyhow::Result<usize> {
-        Ok(self.oxygen_generator_rating()? * self.co2_scrubber_rating()?)
+        Ok(self.oxygen_generator_rating()?.checked_mul(self.co2_scrubber_rating()?).ok_or(Overflow)?)
     }
 
     fn oxygen_generator_rating(&self) -> anyhow::Result<usize> {
-        //println!("start oxygen");
-        let mut generator = RatingGenerator::new(self, Box::new(Counter::max), BinaryDigit::One);
-        let r = generator.calculate()?;
-        //println!("end oxygen");
-        Ok(r)
+        RatingCriteria::oxygen_generator().calculate(self)
     }
 
     fn co2_scrubber_rating(&self) -> anyhow::Result<usize> {
-        //println!("start co2");
-        let mut generator = RatingGenerator::new(self, Box::new(Counter::min), BinaryDigit::Zero);
-        let r = generator.calculate()?;
-        //println!("end co2");
-        Ok(r)
+        RatingCriteria::co2_scrubber().calculate(self)
     }
 
+    fn ratings(&self, config : &RatingConfig) -> anyhow::Result<Vec<(String, usize)>> {
+        config.ratings.iter()
+            .map(|(name, criteria)| Ok((name.clone(), criteria.calculate(self)?)))
+            .collect()
+    }
+
+    /// The number made of the most common digit of every column.
     fn gamma_rate(&self) -> anyhow::Result<usize> {
-        let mut bits = BinaryNumber::new();
+        let mut number = Number::new(self.radix);
         for i in 0..self.max_bit_width() {
-            bits.push(self.most_common(i)?)
+            number.push(self.most_common(i)?)?;
         }
-        Ok(bits.into())
+        Ok(number.try_into()?)
     }
 
     fn max_bit_width(&self) -> usize {
-        self.readings[0].bits.len()
+        self.columns.len()
     }
 
-    fn most_common(&self, i : usize) -> anyhow::Result<BinaryDigit> {
-        let mut counter = Counter::default();
-        for reading in self.readings.iter() {
-            counter.push(reading.bits[i]);
+    /// Counts the digits in column `i` of the readings set in `remaining`.
+    fn count(&self, i : usize, remaining : &BitVector) -> ColumnCount {
+        ColumnCount {
+            counts: self.columns[i].iter()
+                .map(|digits| digits.count_ones_and(remaining))
+                .collect(),
         }
+    }
 
-        match counter.max() {
+    fn most_common(&self, i : usize) -> anyhow::Result<Digit> {
+        match self.count(i, &BitVector::filled(self.readings.len())).max() {
             Some(v) => Ok(v),
             None => Err(anyhow::anyhow!("no max found in counter")),
         }
     }
 
-    fn least_common(&self, i : usize) -> anyhow::Result<BinaryDigit> {
-        let mut counter = Counter::default();
-        for reading in self.readings.iter() {
-            counter.push(reading.bits[i]);
-        }
-
-        match counter.min() {
+    fn least_common(&self, i : usize) -> anyhow::Result<Digit> {
+        match self.count(i, &BitVector::filled(self.readings.len())).min() {
             Some(v) => Ok(v),
             None => Err(anyhow::anyhow!("no min found in counter")),
         }
     }
 
+    /// The number made of the least common digit of every column.
     fn epsilon_rate(&self) -> anyhow::Result<usize> {
-        let mut bits = BinaryNumber::new();
+        let mut number = Number::new(self.radix);
         for i in 0..self.max_bit_width() {
-            bits.push(self.least_common(i)?)
+            number.push(self.least_common(i)?)?;
         }
-        Ok(bits.into())
+        Ok(number.try_into()?)
+    }
+
+    /// Parses one reading per line, skipping blank lines but counting them in
+    /// the line numbers of any errors.
+    fn parse(s : &str, radix : u32, options : ValidationOptions) -> Result<(Self, Vec<ValidationError>), ValidationErrors> {
+        let mut readings = vec![];
+        let mut errors = vec![];
+        for (line, text) in s.lines().enumerate().map(|(i, line)| (i + 1, line.trim())).filter(|(_, line)| !line.is_empty()) {
+            match DiagnosticReading::parse(text, radix) {
+                Ok(reading) => readings.push((line, reading)),
+                Err(error) => errors.push(ValidationError::InvalidReading { line, error }),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ValidationErrors(errors));
+        }
+        DiagnosticReport::validate(readings, options)
     }
 }
 
 impl From<Vec<DiagnosticReading>> for DiagnosticReport {
     fn from(readings : Vec<DiagnosticReading>) -> Self {
+        let width = readings.first().map(|reading| reading.width).unwrap_or_default();
+        let radix = readings.first().map(|reading| reading.radix).unwrap_or(2);
+        let mut columns = vec![vec![BitVector::default(); radix as usize]; width];
+        for reading in readings.iter() {
+            for (i, column) in columns.iter_mut().enumerate() {
+                let digit = reading.digit(i);
+                for (d, digits) in column.iter_mut().enumerate() {
+                    digits.push(digit == Some(Digit(d as u32)));
+                }
+            }
+        }
         DiagnosticReport {
-            readings
+            readings,
+            radix,
+            columns,
         }
     }
 }
 
-#[derive(Default, Debug, PartialEq, Clone)]
+/// A reading of digits in any radix from 2 to 36, packed into an integer with
+/// just enough bits per digit for the radix. That allows up to 128 binary
+/// digits. `width` keeps track of leading zeros.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 struct DiagnosticReading {
-    bits : Vec<BinaryDigit>
+    packed : u128,
+    width : usize,
+    radix : u32,
 }
 
+impl DiagnosticReading {
+    fn new(radix : u32) -> Self {
+        DiagnosticReading {
+            packed: 0,
+            width: 0,
+            radix,
+        }
+    }
+
+    fn bits_per_digit(&self) -> usize {
+        (u32::BITS - (self.radix - 1).leading_zeros()) as usize
+    }
+
+    /// The digit in column `i`, counting from the most significant digit.
+    fn digit(&self, i : usize) -> Option<Digit> {
+        if i < self.width {
+            let bits = self.bits_per_digit();
+            let mask = (1 << bits) - 1;
+            Some(Digit((self.packed >> ((self.width - i - 1) * bits) & mask) as u32))
+        } else {
+            None
+        }
+    }
+
+    fn value(&self) -> u128 {
+        (0..self.width)
+            .filter_map(|i| self.digit(i))
+            .fold(0, |value, digit| value * self.radix as u128 + digit.0 as u128)
+    }
+
+    #[cfg(test)]
+    fn digits(&self) -> Vec<Digit> {
+        (0..self.width).filter_map(|i| self.digit(i)).collect()
+    }
+
+    fn parse(s : &str, radix : u32) -> Result<Self, ReadingError> {
+        if !(2..=36).contains(&radix) {
+            return Err(ReadingError::InvalidRadix(radix));
+        }
+        let mut reading = DiagnosticReading::new(radix);
+        let bits = reading.bits_per_digit();
+        for c in s.chars() {
+            let digit = c.to_digit(radix)
+                .ok_or(ReadingError::InvalidDigit { radix, digit: c })?;
+            if (reading.width + 1) * bits > u128::BITS as usize {
+                return Err(ReadingError::TooWide);
+            }
+            reading.packed = reading.packed << bits | digit as u128;
+            reading.width += 1;
+        }
+        Ok(reading)
+    }
+}
+
+/// Readings are binary unless parsed with `DiagnosticReading::parse`.
 impl FromStr for DiagnosticReading {
-    type Err = InputFileError;
+    type Err = ReadingError;
 
     fn from_str(s : &str) -> Result<Self, Self::Err> {
-        let mut bits = vec![];
-        for c in s.chars() {
-            let digit = match c {
-                '1' => Ok(BinaryDigit::One),
-                '0' => Ok(BinaryDigit::Zero),
-                _ => Err(InputFileError::GeneralError(format!("invalid binary digit: {}", c)))
-            }?;
-            bits.push(digit);
-        }
-        Ok(DiagnosticReading {
-            bits,
+        DiagnosticReading::parse(s, 2)
+    }
+}
+
+/// The digit counts of a column along with how evenly they are spread.
+#[derive(Debug, PartialEq, Clone)]
+struct ColumnStats {
+    count : ColumnCount,
+    /// Shannon entropy in bits, zero for a constant column.
+    entropy : f64,
+}
+
+impl ColumnStats {
+    fn new(count : ColumnCount) -> Self {
+        let total = count.total() as f64;
+        let entropy = count.present()
+            .map(|(_, n)| n as f64 / total)
+            .map(|p| -p * p.log2())
+            .sum();
+        ColumnStats {
+            count,
+            entropy,
+        }
+    }
+
+    /// The share of the readings with `digit` in this column.
+    fn ratio(&self, digit : Digit) -> f64 {
+        match self.count.total() {
+            0 => 0.0,
+            total => self.count.count(digit) as f64 / total as f64,
+        }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.count.present().count() <= 1
+    }
+}
+
+/// Statistics over a whole report, see `DiagnosticReport::stats`.
+#[derive(Debug, PartialEq)]
+struct DiagnosticStats {
+    radix : u32,
+    columns : Vec<ColumnStats>,
+    /// Pearson correlation of the digit values of every pair of columns,
+    /// `None` when either column is constant.
+    correlations : Vec<Vec<Option<f64>>>,
+    /// How many readings survive each column of every rating.
+    traces : Vec<(String, Vec<usize>)>,
+}
+
+impl DiagnosticStats {
+    fn constant_columns(&self) -> Vec<usize> {
+        self.columns.iter()
+            .enumerate()
+            .filter(|(_, column)| column.is_constant())
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for DiagnosticStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = (0..self.radix).map(|digit| std::char::from_digit(digit, self.radix).unwrap_or('?'));
+        write!(f, "column")?;
+        for digit in digits.clone() {
+            write!(f, " {:>8}", digit)?;
+        }
+        writeln!(f, " {:>8}", "entropy")?;
+        for (i, column) in self.columns.iter().enumerate() {
+            write!(f, "{:>6}", i)?;
+            for digit in 0..self.radix {
+                write!(f, " {:>8}", column.count.count(Digit(digit)))?;
+            }
+            writeln!(f, " {:>8.4}", column.entropy)?;
+        }
+        write!(f, "ratio ")?;
+        for digit in digits {
+            write!(f, " {:>8}", digit)?;
+        }
+        writeln!(f)?;
+        for (i, column) in self.columns.iter().enumerate() {
+            write!(f, "{:>6}", i)?;
+            for digit in 0..self.radix {
+                write!(f, " {:>8.4}", column.ratio(Digit(digit)))?;
+            }
+            writeln!(f)?;
+        }
+        let constant = self.constant_columns().iter().map(ToString::to_string).collect::<Vec<String>>();
+        writeln!(f, "constant columns: {}", if constant.is_empty() { "none".to_string() } else { constant.join(" ") })?;
+        writeln!(f, "correlation")?;
+        for (i, row) in self.correlations.iter().enumerate() {
+            write!(f, "{:>6}", i)?;
+            for correlation in row {
+                match correlation {
+                    Some(correlation) => write!(f, " {:>6.3}", correlation)?,
+                    None => write!(f, " {:>6}", "-")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        for (name, trace) in self.traces.iter() {
+            writeln!(f, "{}: {}", name, trace.iter().map(ToString::to_string).collect::<Vec<String>>().join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+impl DiagnosticReport {
+    fn stats(&self, config : &RatingConfig) -> Result<DiagnosticStats, RatingConfigError> {
+        let all = BitVector::filled(self.readings.len());
+        let columns : Vec<ColumnStats> = (0..self.max_bit_width())
+            .map(|i| ColumnStats::new(self.count(i, &all)))
+            .collect();
+        let correlations = (0..columns.len())
+            .map(|i| (0..columns.len()).map(|j| self.correlation(i, j)).collect())
+            .collect();
+        let traces = config.ratings.iter()
+            .map(|(name, criteria)| Ok((name.clone(), criteria.filter(self)?.1)))
+            .collect::<Result<_, RatingConfigError>>()?;
+        Ok(DiagnosticStats {
+            radix: self.radix,
+            columns,
+            correlations,
+            traces,
         })
     }
+
+    /// Pearson correlation between the digit values of columns `i` and `j`.
+    /// The product sums come from popcounting every pair of digit planes.
+    fn correlation(&self, i : usize, j : usize) -> Option<f64> {
+        let n = self.readings.len() as f64;
+        let moments = |column : usize| self.columns[column].iter()
+            .enumerate()
+            .map(|(digit, plane)| (digit as f64, plane.count_ones() as f64))
+            .fold((0.0, 0.0), |(sum, squares), (digit, count)| (sum + digit * count, squares + digit * digit * count));
+        let (sum_i, squares_i) = moments(i);
+        let (sum_j, squares_j) = moments(j);
+        let mut products = 0.0;
+        for (a, plane_a) in self.columns[i].iter().enumerate() {
+            for (b, plane_b) in self.columns[j].iter().enumerate() {
+                products += (a * b) as f64 * plane_a.count_ones_and(plane_b) as f64;
+            }
+        }
+        let covariance = n * products - sum_i * sum_j;
+        let variance_i = n * squares_i - sum_i * sum_i;
+        let variance_j = n * squares_j - sum_j * sum_j;
+        if variance_i <= 0.0 || variance_j <= 0.0 {
+            None
+        } else {
+            Some(covariance / (variance_i * variance_j).sqrt())
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone, Copy)]
+enum ReadingError {
+    #[error("radix {0} is not between 2 and 36")]
+    InvalidRadix(u32),
+    #[error("invalid base {radix} digit: {digit}")]
+    InvalidDigit { radix : u32, digit : char },
+    #[error("reading is wider than {} bits", u128::BITS)]
+    TooWide,
+}
+
+#[derive(Error, Debug, PartialEq)]
+enum ValidationError {
+    #[error("diagnostic report is empty")]
+    Empty,
+    #[error("line {line}: {error}")]
+    InvalidReading { line : usize, error : ReadingError },
+    #[error("line {line}: reading has {width} digits, expected {expected}")]
+    InconsistentWidth { line : usize, width : usize, expected : usize },
+    #[error("line {line}: duplicate of line {first}")]
+    Duplicate { line : usize, first : usize },
+    #[error("line {line}: reading is base {radix}, expected base {expected}")]
+    MixedRadix { line : usize, radix : u32, expected : u32 },
+}
+
+/// Every problem found while validating a report.
+#[derive(Error, Debug, PartialEq)]
+#[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<String>>().join(", "))]
+struct ValidationErrors(Vec<ValidationError>);
+
+impl From<ValidationErrors> for InputFileError {
+    fn from(errors : ValidationErrors) -> Self {
+        InputFileError::ParseError(Box::new(errors))
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+struct ValidationOptions {
+    /// Pad readings narrower than the widest one with leading zeros instead of
+    /// reporting them.
+    pad_shorter : bool,
+    /// Fail on duplicate readings instead of only listing them.
+    reject_duplicates : bool,
+}
+
+impl DiagnosticReport {
+    /// Checks readings, each paired with its line number, before building a
+    /// report. Readings are expected to be as wide as the widest reading and in
+    /// the radix of the first one. Duplicates are returned alongside the report
+    /// unless `reject_duplicates` makes them errors.
+    fn validate(mut readings : Vec<(usize, DiagnosticReading)>, options : ValidationOptions) -> Result<(Self, Vec<ValidationError>), ValidationErrors> {
+        if readings.is_empty() {
+            return Err(ValidationErrors(vec![ValidationError::Empty]));
+        }
+
+        let expected = readings.iter().map(|(_, reading)| reading.width).max().unwrap_or_default();
+        let radix = readings[0].1.radix;
+        let mut errors = vec![];
+        let mut duplicates = vec![];
+        let mut seen = HashMap::new();
+        for (line, reading) in readings.iter_mut() {
+            let line = *line;
+            if reading.radix != radix {
+                errors.push(ValidationError::MixedRadix {
+                    line,
+                    radix: reading.radix,
+                    expected: radix,
+                });
+            }
+            if reading.width < expected && options.pad_shorter {
+                reading.width = expected;
+            }
+            if reading.width != expected {
+                errors.push(ValidationError::InconsistentWidth {
+                    line,
+                    width: reading.width,
+                    expected,
+                });
+            }
+            match seen.entry(*reading) {
+                Entry::Occupied(first) => {
+                    duplicates.push(ValidationError::Duplicate {
+                        line,
+                        first: *first.get(),
+                    });
+                },
+                Entry::Vacant(entry) => {
+                    entry.insert(line);
+                },
+            }
+        }
+
+        if options.reject_duplicates {
+            errors.append(&mut duplicates);
+            errors.sort_by_key(ValidationError::line);
+        }
+        if errors.is_empty() {
+            let readings : Vec<DiagnosticReading> = readings.into_iter().map(|(_, reading)| reading).collect();
+            Ok((readings.into(), duplicates))
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+}
+
+impl ValidationError {
+    fn line(&self) -> usize {
+        match self {
+            ValidationError::Empty => 0,
+            ValidationError::InvalidReading { line, .. }
+                | ValidationError::InconsistentWidth { line, .. }
+                | ValidationError::Duplicate { line, .. }
+                | ValidationError::MixedRadix { line, .. } => *line,
+        }
+    }
 }
 
-impl TryFrom<InputFile> for DiagnosticReport {
-    type Error = InputFileError;
+/// Binary readings, keeping duplicates.
+impl FromStr for DiagnosticReport {
+    type Err = ValidationErrors;
 
-    fn try_from(input_file : InputFile) -> Result<Self, Self::Error> {
-        let diagnostic_readings : Vec<DiagnosticReading> = input_file.try_into()?;
-        Ok(diagnostic_readings.into())
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        Ok(DiagnosticReport::parse(s, 2, ValidationOptions::default())?.0)
     }
 }
 
@@ -305,37 +985,53 @@ impl TryFrom<InputFile> for DiagnosticReport {
 mod test {
     use rstest::rstest;
     use super::*;
-    use super::BinaryDigit::*;
-    use aoc2021::InputFile;
 
     #[rstest]
-    #[case("00100", vec![Zero, Zero, One, Zero, Zero])]
-    #[case("11110", vec![One, One, One, One, Zero])]
-    fn parse_binary_digits(#[case] s : &str, #[case] bits : Vec<BinaryDigit>) -> anyhow::Result<()> {
+    #[case("00100", vec![0, 0, 1, 0, 0])]
+    #[case("11110", vec![1, 1, 1, 1, 0])]
+    fn parse_binary_digits(#[case] s : &str, #[case] bits : Vec<u32>) -> anyhow::Result<()> {
         let parsed : DiagnosticReading = FromStr::from_str(s)?;
-        assert_eq!(parsed, DiagnosticReading { bits });
+        assert_eq!(parsed.digits(), bits.into_iter().map(Digit).collect::<Vec<Digit>>());
         Ok(())
     }
 
+    #[rstest]
+    #[case("2101", 3, vec![2, 1, 0, 1], 64)]
+    #[case("ff0a", 16, vec![15, 15, 0, 10], 0xff0a)]
+    #[case("z9", 36, vec![35, 9], 35 * 36 + 9)]
+    fn parse_radix_digits(#[case] s : &str, #[case] radix : u32, #[case] digits : Vec<u32>, #[case] value : u128) -> anyhow::Result<()> {
+        let parsed = DiagnosticReading::parse(s, radix)?;
+        assert_eq!(parsed.digits(), digits.into_iter().map(Digit).collect::<Vec<Digit>>());
+        assert_eq!(parsed.value(), value);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_radix_digits() {
+        assert!(DiagnosticReading::parse("1012", 2).is_err());
+        assert!(DiagnosticReading::parse("10", 1).is_err());
+        assert!(DiagnosticReading::parse("10", 37).is_err());
+        // four bits per hex digit
+        assert!(DiagnosticReading::parse(&"f".repeat(32), 16).is_ok());
+        assert!(DiagnosticReading::parse(&"f".repeat(33), 16).is_err());
+    }
+
     #[test]
     fn calculate() -> anyhow::Result<()> {
-        let input = InputFile {
-            data: vec![
-                "00100".to_string(),
-                "11110".to_string(),
-                "10110".to_string(),
-                "10111".to_string(),
-                "10101".to_string(),
-                "01111".to_string(),
-                "00111".to_string(),
-                "11100".to_string(),
-                "10000".to_string(),
-                "11001".to_string(),
-                "00010".to_string(),
-                "01010".to_string(),
-            ],
-        };
-        let diagnostic_report : DiagnosticReport = input.try_into()?;
+        let diagnostic_report : DiagnosticReport = FromStr::from_str("
+            00100
+            11110
+            10110
+            10111
+            10101
+            01111
+            00111
+            11100
+            10000
+            11001
+            00010
+            01010
+        ")?;
         assert_eq!(9, diagnostic_report.epsilon_rate()?);
         assert_eq!(22, diagnostic_report.gamma_rate()?);
         assert_eq!(198, diagnostic_report.result()?);
@@ -346,4 +1042,377 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn bit_vector() {
+        let mut bits = BitVector::default();
+        for i in 0..130 {
+            bits.push(i % 3 == 0);
+        }
+
+        assert_eq!(bits.len, 130);
+        assert_eq!(bits.count_ones(), 44);
+        assert!(bits.get(129));
+        assert!(!bits.get(128));
+        assert!(!bits.get(130));
+        assert_eq!(BitVector::filled(130).count_ones(), 130);
+        assert_eq!(bits.count_ones_and(&BitVector::filled(130)), 44);
+        assert_eq!(bits.and_not(&BitVector::filled(130)).first_one(), None);
+        assert_eq!(BitVector::filled(130).and_not(&bits).first_one(), Some(1));
+    }
+
+    #[test]
+    fn wide_readings() -> anyhow::Result<()> {
+        let wide = "1".repeat(128);
+        let reading : DiagnosticReading = FromStr::from_str(&wide)?;
+        assert_eq!(reading.value(), u128::MAX);
+
+        let too_wide : Result<DiagnosticReading, _> = FromStr::from_str(&format!("0{}", wide));
+        assert!(too_wide.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn overflowing_rates() -> anyhow::Result<()> {
+        let ones = "1".repeat(70);
+        let even = format!("{}0", "1".repeat(69));
+        let zeros = "0".repeat(70);
+        let report : DiagnosticReport = readings(&[&ones, &even, &zeros])?.into();
+
+        assert_eq!(report.epsilon_rate()?, 1);
+        assert_eq!(report.gamma_rate().unwrap_err().downcast::<Overflow>()?, Overflow);
+        assert!(report.result().is_err());
+        assert_eq!(report.co2_scrubber_rating()?, 0);
+        assert_eq!(report.oxygen_generator_rating().unwrap_err().downcast::<Overflow>()?, Overflow);
+
+        let mut number = Number::new(36);
+        for _ in 0..24 {
+            number.push(Digit(35))?;
+        }
+        assert_eq!(number.push(Digit(35)), Err(Overflow));
+        Ok(())
+    }
+
+    #[test]
+    fn puzzle() -> anyhow::Result<()> {
+        let diagnostic_report : DiagnosticReport = load_input_text(3)?;
+        assert_eq!(diagnostic_report.result()?, 4147524);
+        assert_eq!(diagnostic_report.life_support_rating()?, 3570354);
+        Ok(())
+    }
+
+    #[test]
+    fn many_readings() -> anyhow::Result<()> {
+        let readings : Vec<DiagnosticReading> = (0..200_000_u128)
+            .map(|i| DiagnosticReading {
+                packed: (i * 2_654_435_761) % (1 << 20),
+                width: 20,
+                radix: 2,
+            })
+            .collect();
+        let gamma = (0..20)
+            .map(|column| readings.iter().filter(|reading| reading.digit(column) == Some(Digit(1))).count() * 2 > readings.len())
+            .fold(0, |value, one| value << 1 | one as usize);
+        let report : DiagnosticReport = readings.into();
+
+        assert_eq!(report.gamma_rate()?, gamma);
+        assert!(report.oxygen_generator_rating().is_ok());
+        assert!(report.co2_scrubber_rating().is_ok());
+        Ok(())
+    }
+
+    fn sample() -> anyhow::Result<DiagnosticReport> {
+        let readings : Vec<DiagnosticReading> = ["00100", "11110", "10110", "10111", "10101", "01111", "00111", "11100", "10000", "11001", "00010", "01010"]
+            .iter()
+            .map(|s| FromStr::from_str(s))
+            .collect::<Result<_, _>>()?;
+        Ok(readings.into())
+    }
+
+    #[rstest]
+    #[case(Criterion::MostCommon, TiePolicy::Prefer(Digit(1)), 23)]
+    #[case(Criterion::LeastCommon, TiePolicy::Prefer(Digit(0)), 10)]
+    #[case(Criterion::Threshold { digit: Digit(1), ratio: 0.5 }, TiePolicy::Prefer(Digit(1)), 23)]
+    #[case(Criterion::Threshold { digit: Digit(1), ratio: 0.7 }, TiePolicy::Prefer(Digit(1)), 2)]
+    #[case(Criterion::MostCommon, TiePolicy::Prefer(Digit(0)), 22)]
+    #[case(Criterion::MostCommon, TiePolicy::Lowest, 22)]
+    #[case(Criterion::LeastCommon, TiePolicy::Highest, 15)]
+    #[case(Criterion::Threshold { digit: Digit(0), ratio: 0.3 }, TiePolicy::Lowest, 2)]
+    fn rating_criteria(#[case] criterion : Criterion, #[case] tie : TiePolicy, #[case] expected : usize) -> anyhow::Result<()> {
+        assert_eq!(RatingCriteria::new(criterion, tie).calculate(&sample()?)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn skip_ties() -> anyhow::Result<()> {
+        let readings : Vec<DiagnosticReading> = ["100", "110", "001", "011"]
+            .iter()
+            .map(|s| FromStr::from_str(s))
+            .collect::<Result<_, _>>()?;
+        let report : DiagnosticReport = readings.into();
+
+        // columns 0 and 1 are tied, column 2 is not
+        let skip = RatingCriteria::new(Criterion::MostCommon, TiePolicy::Skip);
+        assert!(skip.calculate(&report).is_err());
+        let prefer = RatingCriteria::new(Criterion::MostCommon, TiePolicy::Prefer(Digit(1)));
+        assert_eq!(prefer.calculate(&report)?, 0b110);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_criterion() -> anyhow::Result<()> {
+        // alternate between the most and least common digit
+        let alternating = RatingCriteria::new(Criterion::alternating(), TiePolicy::Prefer(Digit(1)));
+        assert_eq!(alternating.calculate(&sample()?)?, 0b11100);
+
+        let config : RatingConfig = FromStr::from_str("alternating = alternating tie=one")?;
+        assert_eq!(sample()?.ratings(&config)?, vec![("alternating".to_string(), 0b11100)]);
+
+        // a closure picking a digit the report doesn't have fails instead of
+        // indexing past the columns
+        let nines = RatingCriteria::new(Criterion::custom(|_, _| Some(Digit(9))), TiePolicy::Lowest);
+        assert_eq!(nines.calculate(&sample()?).unwrap_err().downcast::<RatingConfigError>()?, RatingConfigError::DigitOutOfRange { digit: 9, radix: 2 });
+        Ok(())
+    }
+
+    #[test]
+    fn rating_config() -> anyhow::Result<()> {
+        let config : RatingConfig = FromStr::from_str("
+            # the puzzle ratings
+            oxygen = most_common
+            co2 = least_common tie=zero
+
+            strict = threshold 0.7
+        ")?;
+
+        assert_eq!(sample()?.ratings(&config)?, vec![
+            ("oxygen".to_string(), 23),
+            ("co2".to_string(), 10),
+            ("strict".to_string(), 2),
+        ]);
+        assert_eq!(sample()?.ratings(&RatingConfig::default())?, vec![
+            ("oxygen".to_string(), 23),
+            ("co2".to_string(), 10),
+        ]);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("oxygen", RatingConfigError::MissingName { line: 1 }, "line 1: expected name = criterion")]
+    #[case("oxygen = most", RatingConfigError::UnknownCriterion { line: 1, criterion: "most".to_string() }, "line 1: unknown criterion most")]
+    #[case("\noxygen = threshold", RatingConfigError::MissingRatio { line: 2 }, "line 2: threshold expects a ratio")]
+    #[case("oxygen = threshold -0.5 9", RatingConfigError::InvalidRatio { line: 1, ratio: "-0.5".to_string() }, "line 1: ratio -0.5 is not between 0 and 1")]
+    #[case("oxygen = threshold 1.5", RatingConfigError::InvalidRatio { line: 1, ratio: "1.5".to_string() }, "line 1: ratio 1.5 is not between 0 and 1")]
+    #[case("oxygen = threshold NaN", RatingConfigError::InvalidRatio { line: 1, ratio: "NaN".to_string() }, "line 1: ratio NaN is not between 0 and 1")]
+    #[case("oxygen = threshold 0.5 x7", RatingConfigError::InvalidDigit { line: 1, digit: "x7".to_string() }, "line 1: invalid digit x7")]
+    #[case("oxygen = most_common tie=maybe", RatingConfigError::InvalidTie { line: 1, tie: "maybe".to_string() }, "line 1: unexpected tie=maybe")]
+    #[case("# comment\noxygen =", RatingConfigError::MissingCriterion { line: 2 }, "line 2: missing criterion")]
+    #[case("oxygen = most_common lowest", RatingConfigError::UnexpectedToken { line: 1, token: "lowest".to_string() }, "line 1: unexpected lowest")]
+    fn rating_config_errors(#[case] config : &str, #[case] expected : RatingConfigError, #[case] message : &str) {
+        let parsed : Result<RatingConfig, RatingConfigError> = FromStr::from_str(config);
+        let error = parsed.expect_err(config);
+        assert_eq!(error.to_string(), message);
+        assert_eq!(error, expected);
+    }
+
+    #[rstest]
+    #[case("nines = threshold 0.5 9", 9)]
+    #[case("nines = most_common tie=9", 9)]
+    #[case("twos = least_common tie=2", 2)]
+    fn digits_outside_the_radix(#[case] config : &str, #[case] digit : u32) -> anyhow::Result<()> {
+        let config : RatingConfig = FromStr::from_str(config)?;
+        let error = sample()?.ratings(&config).unwrap_err().downcast::<RatingConfigError>()?;
+        assert_eq!(error, RatingConfigError::DigitOutOfRange { digit, radix: 2 });
+        assert_eq!(sample()?.stats(&config).map(|_| ()), Err(RatingConfigError::DigitOutOfRange { digit, radix: 2 }));
+        Ok(())
+    }
+
+    #[test]
+    fn empty_selection_keeps_every_reading() {
+        let threshold = RatingCriteria::new(Criterion::Threshold { digit: Digit(1), ratio: 0.5 }, TiePolicy::Lowest);
+        assert_eq!(threshold.select(0, &ColumnCount::default()), None);
+    }
+
+    fn readings(readings : &[&str]) -> anyhow::Result<Vec<DiagnosticReading>> {
+        Ok(readings.iter()
+            .map(|s| FromStr::from_str(s))
+            .collect::<Result<_, _>>()?)
+    }
+
+    fn numbered(readings : Vec<DiagnosticReading>) -> Vec<(usize, DiagnosticReading)> {
+        readings.into_iter().enumerate().map(|(i, reading)| (i + 1, reading)).collect()
+    }
+
+    #[test]
+    fn validate_empty() {
+        assert_eq!(DiagnosticReport::validate(vec![], ValidationOptions::default()), Err(ValidationErrors(vec![ValidationError::Empty])));
+
+        let empty : Result<DiagnosticReport, _> = FromStr::from_str("\n\n");
+        assert!(empty.is_err());
+    }
+
+    #[test]
+    fn validate_widths_and_duplicates() -> anyhow::Result<()> {
+        let readings = numbered(readings(&["00100", "110", "00100", "10110", "00100"])?);
+
+        let errors = DiagnosticReport::validate(readings.clone(), ValidationOptions::default());
+        assert_eq!(errors, Err(ValidationErrors(vec![
+            ValidationError::InconsistentWidth { line: 2, width: 3, expected: 5 },
+        ])));
+
+        let errors = DiagnosticReport::validate(readings.clone(), ValidationOptions {
+            pad_shorter: false,
+            reject_duplicates: true,
+        });
+        assert_eq!(errors, Err(ValidationErrors(vec![
+            ValidationError::InconsistentWidth { line: 2, width: 3, expected: 5 },
+            ValidationError::Duplicate { line: 3, first: 1 },
+            ValidationError::Duplicate { line: 5, first: 1 },
+        ])));
+        assert_eq!(errors.unwrap_err().to_string(), "line 2: reading has 3 digits, expected 5, line 3: duplicate of line 1, line 5: duplicate of line 1");
+
+        let (report, duplicates) = DiagnosticReport::validate(readings, ValidationOptions {
+            pad_shorter: true,
+            reject_duplicates: false,
+        })?;
+        assert_eq!(report.readings[1].digits(), vec![Digit(0), Digit(0), Digit(1), Digit(1), Digit(0)]);
+        assert_eq!(report.max_bit_width(), 5);
+        assert_eq!(duplicates, vec![
+            ValidationError::Duplicate { line: 3, first: 1 },
+            ValidationError::Duplicate { line: 5, first: 1 },
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn padded_duplicates() -> anyhow::Result<()> {
+        let errors = DiagnosticReport::validate(numbered(readings(&["0110", "110"])?), ValidationOptions {
+            pad_shorter: true,
+            reject_duplicates: true,
+        });
+        assert_eq!(errors, Err(ValidationErrors(vec![ValidationError::Duplicate { line: 2, first: 1 }])));
+        Ok(())
+    }
+
+    #[test]
+    fn file_line_numbers() -> anyhow::Result<()> {
+        let errors = DiagnosticReport::parse("0110\n\n110\n\n0110\n", 2, ValidationOptions {
+            pad_shorter: false,
+            reject_duplicates: true,
+        });
+        assert_eq!(errors.map(|_| ()), Err(ValidationErrors(vec![
+            ValidationError::InconsistentWidth { line: 3, width: 3, expected: 4 },
+            ValidationError::Duplicate { line: 5, first: 1 },
+        ])));
+        let error = InputFileError::from(ValidationErrors(vec![ValidationError::Empty]));
+        assert!(matches!(error, InputFileError::ParseError(error) if error.downcast_ref::<ValidationErrors>().is_some()));
+
+        let (report, duplicates) = DiagnosticReport::parse("0110\n\n110\n\n0110\n", 2, ValidationOptions {
+            pad_shorter: true,
+            reject_duplicates: false,
+        })?;
+        assert_eq!(report.readings.len(), 3);
+        assert_eq!(duplicates, vec![
+            ValidationError::Duplicate { line: 3, first: 1 },
+            ValidationError::Duplicate { line: 5, first: 1 },
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn reading_errors_carry_line_numbers() {
+        let errors = DiagnosticReport::parse("0110\n\n01x0\n0110\n2110\n", 2, ValidationOptions::default()).map(|_| ());
+        assert_eq!(errors, Err(ValidationErrors(vec![
+            ValidationError::InvalidReading { line: 3, error: ReadingError::InvalidDigit { radix: 2, digit: 'x' } },
+            ValidationError::InvalidReading { line: 5, error: ReadingError::InvalidDigit { radix: 2, digit: '2' } },
+        ])));
+        assert_eq!(errors.unwrap_err().to_string(), "line 3: invalid base 2 digit: x, line 5: invalid base 2 digit: 2");
+        assert_eq!(DiagnosticReport::parse("01", 37, ValidationOptions::default()).map(|_| ()), Err(ValidationErrors(vec![
+            ValidationError::InvalidReading { line: 1, error: ReadingError::InvalidRadix(37) },
+        ])));
+    }
+
+    fn ternary() -> anyhow::Result<DiagnosticReport> {
+        Ok(DiagnosticReport::parse("210\n201\n120\n102\n012\n222\n211", 3, ValidationOptions::default())?.0)
+    }
+
+    #[test]
+    fn ternary_rates() -> anyhow::Result<()> {
+        let report = ternary()?;
+        // modes 2, 1 and 2, anti-modes 0, 0 (tied with 2) and 0 (tied with 1)
+        assert_eq!(report.gamma_rate()?, 2 * 9 + 3 + 2);
+        assert_eq!(report.epsilon_rate()?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn ternary_ratings() -> anyhow::Result<()> {
+        let report = ternary()?;
+        // 210, 201, 222, 211 -> 210, 211 -> 211
+        assert_eq!(report.oxygen_generator_rating()?, 2 * 9 + 3 + 1);
+        // 012
+        assert_eq!(report.co2_scrubber_rating()?, 5);
+        let twos = RatingCriteria::new(Criterion::Threshold { digit: Digit(2), ratio: 0.3 }, TiePolicy::Lowest);
+        // 210, 201, 222, 211 -> 210, 211 -> 210
+        assert_eq!(twos.calculate(&report)?, 2 * 9 + 3);
+
+        // 2 is a digit of ternary reports, unlike binary ones
+        let tie : RatingConfig = FromStr::from_str("twos = least_common tie=2")?;
+        assert!(report.ratings(&tie).is_ok());
+
+        let config : RatingConfig = FromStr::from_str("twos = threshold 0.3 2 tie=lowest")?;
+        assert_eq!(report.ratings(&config)?, vec![("twos".to_string(), 21)]);
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_radix() -> anyhow::Result<()> {
+        let readings = vec![DiagnosticReading::parse("12", 3)?, DiagnosticReading::parse("12", 10)?];
+        assert_eq!(DiagnosticReport::validate(numbered(readings), ValidationOptions::default()), Err(ValidationErrors(vec![
+            ValidationError::MixedRadix { line: 2, radix: 10, expected: 3 },
+        ])));
+        Ok(())
+    }
+
+    #[test]
+    fn column_stats() -> anyhow::Result<()> {
+        let stats = sample()?.stats(&RatingConfig::default())?;
+
+        assert_eq!(stats.columns[0].count.counts, vec![5, 7]);
+        assert!((stats.columns[0].ratio(Digit(1)) - 7.0 / 12.0).abs() < 1e-9);
+        assert!((stats.columns[0].entropy - 0.979868756).abs() < 1e-6);
+        assert!(stats.constant_columns().is_empty());
+        assert_eq!(stats.traces, vec![
+            ("oxygen".to_string(), vec![7, 4, 3, 2, 1]),
+            ("co2".to_string(), vec![5, 2, 1]),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn constant_columns_and_correlation() -> anyhow::Result<()> {
+        let report : DiagnosticReport = readings(&["1001", "1010", "1101", "1110"])?.into();
+        let stats = report.stats(&RatingConfig::default())?;
+
+        assert_eq!(stats.constant_columns(), vec![0]);
+        assert_eq!(stats.columns[0].entropy, 0.0);
+        assert_eq!(stats.columns[1].entropy, 1.0);
+        assert_eq!(stats.correlations[0][1], None);
+        assert_eq!(stats.correlations[1][1], Some(1.0));
+        assert_eq!(stats.correlations[1][2], Some(0.0));
+        // the last two columns always differ
+        assert_eq!(stats.correlations[2][3], Some(-1.0));
+        assert_eq!(stats.correlations[3][2], Some(-1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn ternary_stats() -> anyhow::Result<()> {
+        let stats = ternary()?.stats(&RatingConfig::default())?;
+        assert_eq!(stats.columns[0].count.counts, vec![1, 2, 4]);
+        assert!((stats.columns[2].entropy - (2.0 * (2.0 / 7.0) * (7.0_f64 / 2.0).log2() + (3.0 / 7.0) * (7.0_f64 / 3.0).log2())).abs() < 1e-9);
+        assert_eq!(stats.traces[0].1, vec![4, 2, 1]);
+        assert!(stats.to_string().contains("oxygen: 4 -> 2 -> 1"));
+        Ok(())
+    }
 }
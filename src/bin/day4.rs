@@ -1,14 +1,78 @@
-use aoc2021::input::{InputFileError, InputFile, load_input};
+use aoc2021::input::{InputFileError, InputFile, InputSections, load_input_sections};
+use aoc2021::Rng;
+use std::collections::HashMap;
+use thiserror::Error;
 
 fn main() -> anyhow::Result<()> {
-    let mut game: Game = load_input(4)?;
+    let mut patterns = vec![];
+    let mut ranks = vec![];
+    let mut scan = false;
+    let mut solve = vec![];
+    let mut odds = None;
+    let mut tournament = None;
+    let mut elimination = Elimination::Keep;
+    for arg in std::env::args().skip(1) {
+        if arg == "--scan" {
+            scan = true;
+        } else if let Some(path) = arg.strip_prefix("--tournament=") {
+            tournament = Some(path.to_string());
+        } else if let Some(rule) = arg.strip_prefix("--eliminate=") {
+            elimination = rule.parse()?;
+        } else if let Some(trials) = arg.strip_prefix("--odds=") {
+            odds = Some(match trials.split_once(',') {
+                Some((trials, seed)) => (trials.parse::<usize>()?, seed.parse::<u64>()?),
+                None => (trials.parse::<usize>()?, 0),
+            });
+        } else if let Some(goal) = arg.strip_prefix("--solve=") {
+            solve.push(goal.to_string());
+        } else if let Some(path) = arg.strip_prefix("--mask=") {
+            patterns.push(WinPattern::Custom(std::fs::read_to_string(path)?.parse()?));
+        } else if let Some(rank) = arg.strip_prefix("--rank=") {
+            ranks.push(rank.parse::<usize>()?);
+        } else {
+            patterns.extend(arg.split(',').map(str::parse).collect::<Result<Vec<WinPattern>, _>>()?);
+        }
+    }
+    let custom = !patterns.is_empty();
+    if !custom {
+        patterns = WinPattern::defaults();
+    }
+
+    if let Some(path) = tournament {
+        let mut tournament : Tournament = InputSections::new(std::fs::read_to_string(path)?).try_into()?;
+        tournament.with_patterns(patterns)?;
+        tournament.elimination = elimination;
+        let (rounds, standings) = tournament.play();
+        for (i, round) in rounds.iter().enumerate() {
+            match round.winner {
+                Some(winner) => println!("round {}: board {} won, eliminated {:?}", i + 1, winner, round.eliminated),
+                None => println!("round {}: no winner, eliminated {:?}", i + 1, round.eliminated),
+            }
+        }
+        for (place, standing) in standings.iter().enumerate() {
+            println!("{}. {}", place + 1, standing);
+        }
+        return Ok(());
+    }
 
-    match game.simulate() {
+    let mut game: Game = load_input_sections(4)?;
+    game.with_patterns(patterns)?;
+    let timeline = if scan {
+        game.timeline()
+    } else {
+        game.indexed_timeline()
+    };
+
+    match timeline.first_winner() {
         SimulationResult::Winner(WinningBoard {
             last_number,
             board,
+            pattern,
         }) => {
             println!("part1: {}", last_number * board.sum_of_all_unmarked_numbers());
+            if custom {
+                println!("part1 won with {}", pattern);
+            }
         },
         x => {
             println!("part1 failed {:?}", x);
@@ -16,13 +80,16 @@ fn main() -> anyhow::Result<()> {
         },
     }
 
-    let mut game: Game = load_input(4)?;
-    match game.find_last_board_to_win() {
+    match timeline.last_winner() {
         SimulationResult::Winner(WinningBoard {
             last_number,
             board,
+            pattern,
         }) => {
             println!("part2: {}", last_number * board.sum_of_all_unmarked_numbers());
+            if custom {
+                println!("part2 won with {}", pattern);
+            }
         },
         x => {
             println!("part2 failed {:?}", x);
@@ -30,63 +97,397 @@ fn main() -> anyhow::Result<()> {
         },
     }
 
+    for rank in ranks {
+        match timeline.rank(rank) {
+            Some(win) => println!("rank {}: {}", rank, win),
+            None => println!("rank {}: only {} boards win", rank, timeline.wins.len()),
+        }
+    }
+    if !timeline.never_won.is_empty() {
+        println!("never won: {:?}", timeline.never_won);
+    }
+
+    if let Some((trials, seed)) = odds {
+        let results = if trials == 0 {
+            odds::exact(&game.boards, &game.patterns, &game.to_draw)?
+        } else {
+            odds::monte_carlo(&game.boards, &game.patterns, &game.to_draw, trials, &mut Rng::new(seed))
+        };
+        for (board, odds) in results.iter().enumerate() {
+            println!("board {}: {}", board, odds);
+        }
+    }
+
+    if let Some(board) = game.boards.first() {
+        // every number up to the largest drawn, and a few above it that
+        // are never drawn
+        let max = game.to_draw.iter().max().copied().unwrap_or_default();
+        let pool = (0..=max + board.rows.max(board.columns) as i64).collect();
+        let solver = BoardSolver::new(&game.to_draw, board.rows, board.columns).with_pool(pool);
+        for goal in solve {
+            let solved = match goal.split_once(',') {
+                Some((draw, score)) => solver.winner_at(draw.parse()?, score.parse()?)?,
+                None if goal == "earliest" => solver.earliest_winner()?,
+                None if goal == "never" => solver.never_winner()?,
+                None => return Err(anyhow::anyhow!("unknown goal {}", goal)),
+            };
+            print!("{}", solved);
+            match solver.play(&solved).wins.first() {
+                Some(win) => println!("{}", win),
+                None => println!("never wins"),
+            }
+        }
+    }
 
     Ok(())
 }
 
-#[derive(Default)]
 struct Game {
     to_draw : Vec<i64>,
     boards : Vec<Board>,
+    patterns : Vec<WinPattern>,
 }
 
 impl Game {
-    fn draw_number(&mut self, num : i64) {
-        for board in self.boards.iter_mut() {
-            board.draw_number(num);
-        }
+    /// Replaces the patterns that win a board. Custom masks have to be the
+    /// size of the boards.
+    fn with_patterns(&mut self, patterns : Vec<WinPattern>) -> Result<(), BoardError> {
+        check_masks(&self.boards, &patterns)?;
+        self.patterns = patterns;
+        Ok(())
     }
 
-    fn winner(&self) -> Option<Board> {
-        for board in self.boards.iter() {
-            if board.is_winner() {
-                return Some(*board)
+    /// Plays every number once, recording each board the moment it wins.
+    /// Boards stop being marked once they have won, so the boards in the
+    /// timeline are left as they were when they won.
+    fn timeline(&self) -> Timeline {
+        let mut boards = self.boards.clone();
+        let mut won = vec![false; boards.len()];
+        let mut wins = vec![];
+        for (draw, number) in self.to_draw.iter().enumerate() {
+            for (index, board) in boards.iter_mut().enumerate() {
+                if won[index] {
+                    continue;
+                }
+                board.draw_number(*number);
+                if let Some(pattern) = board.winning_pattern(&self.patterns) {
+                    won[index] = true;
+                    wins.push(Win {
+                        draw,
+                        number: *number,
+                        board: index,
+                        score: number * board.sum_of_all_unmarked_numbers(),
+                        pattern: pattern.clone(),
+                    });
+                }
             }
         }
-        None
+        Timeline {
+            wins,
+            never_won: won.iter().enumerate().filter(|(_, won)| !**won).map(|(index, _)| index).collect(),
+            boards,
+        }
     }
 
-    fn simulate(&mut self) -> SimulationResult {
-        for number in self.to_draw.clone().into_iter() {
-            self.draw_number(number);
-            match self.winner() {
-                Some(board) => return SimulationResult::Winner(WinningBoard {
-                    last_number: number,
-                    board: board,
-                }),
-                None => {}
+    /// The same timeline as `timeline`, but each draw only visits the cells
+    /// holding the number drawn and checks the boards they are on against
+    /// running hit counts, instead of scanning every cell of every board.
+    fn indexed_timeline(&self) -> Timeline {
+        let mut cells : HashMap<i64, Vec<(usize, usize, usize)>> = HashMap::new();
+        for (index, board) in self.boards.iter().enumerate() {
+            for x in 0..board.rows {
+                for y in 0..board.columns {
+                    cells.entry(board.number(x, y)).or_default().push((index, x, y));
+                }
             }
         }
-        SimulationResult::Draw
+
+        let mut boards = self.boards.clone();
+        let mut hits : Vec<HitCounts> = boards.iter().map(|board| HitCounts::new(board, &self.patterns)).collect();
+        let mut won = vec![false; boards.len()];
+        let mut wins = vec![];
+        for (draw, number) in self.to_draw.iter().enumerate() {
+            let mut touched = vec![];
+            for (index, x, y) in cells.get(number).into_iter().flatten() {
+                if won[*index] || !boards[*index].mark(*x, *y) {
+                    continue;
+                }
+                hits[*index].mark(&boards[*index], &self.patterns, *x, *y);
+                touched.push(*index);
+            }
+            // cells are indexed board by board, so `touched` is already sorted
+            touched.dedup();
+            for index in touched {
+                if let Some(pattern) = hits[index].winning_pattern(&boards[index], &self.patterns) {
+                    won[index] = true;
+                    wins.push(Win {
+                        draw,
+                        number: *number,
+                        board: index,
+                        score: number * hits[index].unmarked,
+                        pattern: pattern.clone(),
+                    });
+                }
+            }
+        }
+        Timeline {
+            wins,
+            never_won: won.iter().enumerate().filter(|(_, won)| !**won).map(|(index, _)| index).collect(),
+            boards,
+        }
     }
+}
 
-    fn find_last_board_to_win(&mut self) -> SimulationResult {
-        for number in self.to_draw.clone().into_iter() {
-            self.draw_number(number);
-            if self.boards.len() == 1 && self.boards[0].is_winner() {
-                return SimulationResult::Winner(WinningBoard {
-                    last_number: number,
-                    board: self.boards[0],
-                });
+/// How much of every win pattern a board has marked, kept up to date one
+/// marked cell at a time so checking for a win doesn't look at the cells.
+#[derive(Debug)]
+struct HitCounts {
+    rows : Vec<usize>,
+    columns : Vec<usize>,
+    full_rows : usize,
+    full_columns : usize,
+    diagonal : usize,
+    anti_diagonal : usize,
+    corners : usize,
+    marked : usize,
+    unmarked : i64,
+    /// Required cells marked for each pattern, only used by custom masks.
+    masks : Vec<usize>,
+}
+
+impl HitCounts {
+    fn new(board : &Board, patterns : &[WinPattern]) -> Self {
+        HitCounts {
+            rows: vec![0; board.rows],
+            columns: vec![0; board.columns],
+            full_rows: 0,
+            full_columns: 0,
+            diagonal: 0,
+            anti_diagonal: 0,
+            corners: 0,
+            marked: 0,
+            unmarked: board.sum_of_all_unmarked_numbers(),
+            masks: vec![0; patterns.len()],
+        }
+    }
+
+    fn mark(&mut self, board : &Board, patterns : &[WinPattern], x : usize, y : usize) {
+        self.rows[x] += 1;
+        if self.rows[x] == board.columns {
+            self.full_rows += 1;
+        }
+        self.columns[y] += 1;
+        if self.columns[y] == board.rows {
+            self.full_columns += 1;
+        }
+        if x == y {
+            self.diagonal += 1;
+        }
+        if x + y + 1 == board.columns {
+            self.anti_diagonal += 1;
+        }
+        if board.corners().contains(&(x, y)) {
+            self.corners += 1;
+        }
+        self.marked += 1;
+        self.unmarked -= board.number(x, y);
+        for (pattern, hits) in patterns.iter().zip(self.masks.iter_mut()) {
+            if let WinPattern::Custom(mask) = pattern {
+                if mask.size() == board.size() && mask.cells[x * board.columns + y] {
+                    *hits += 1;
+                }
             }
-            self.boards = self.boards.clone().into_iter()
-                .filter(|x| !x.is_winner())
-                .collect();
         }
-        SimulationResult::Draw
+    }
+
+    fn is_complete(&self, board : &Board, pattern : &WinPattern, hits : usize) -> bool {
+        let square = board.rows == board.columns;
+        match pattern {
+            WinPattern::Rows => self.full_rows > 0,
+            WinPattern::Columns => self.full_columns > 0,
+            WinPattern::Diagonals => square && (self.diagonal == board.rows || self.anti_diagonal == board.rows),
+            WinPattern::FourCorners => self.corners == board.distinct_corners(),
+            WinPattern::X => square && self.diagonal == board.rows && self.anti_diagonal == board.rows,
+            WinPattern::Blackout => self.marked == board.data.len(),
+            WinPattern::Custom(mask) => mask.size() == board.size() && hits == mask.required(),
+        }
+    }
+
+    fn winning_pattern<'a>(&self, board : &Board, patterns : &'a [WinPattern]) -> Option<&'a WinPattern> {
+        patterns.iter()
+            .zip(self.masks.iter())
+            .find(|(pattern, hits)| self.is_complete(board, pattern, **hits))
+            .map(|(pattern, _)| pattern)
+    }
+}
+
+/// A board winning on the `draw`th number drawn, both counted from zero.
+#[derive(Debug, PartialEq, Clone)]
+struct Win {
+    draw : usize,
+    number : i64,
+    board : usize,
+    score : i64,
+    pattern : WinPattern,
+}
+
+impl std::fmt::Display for Win {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "board {} won with {} on draw {} ({}), scoring {}", self.board, self.pattern, self.draw, self.number, self.score)
+    }
+}
+
+/// Every win of a game in order. Boards winning on the same draw are in board
+/// order.
+#[derive(Debug, PartialEq)]
+struct Timeline {
+    wins : Vec<Win>,
+    never_won : Vec<usize>,
+    boards : Vec<Board>,
+}
+
+impl Timeline {
+    /// The board that won in place `rank`, counting from one.
+    fn rank(&self, rank : usize) -> Option<&Win> {
+        rank.checked_sub(1).and_then(|index| self.wins.get(index))
+    }
+
+    fn first_winner(&self) -> SimulationResult {
+        self.winning_board(0)
+    }
+
+    fn last_winner(&self) -> SimulationResult {
+        match self.wins.len() {
+            0 => SimulationResult::Draw,
+            n => self.winning_board(n - 1),
+        }
+    }
+
+    fn winning_board(&self, index : usize) -> SimulationResult {
+        match self.wins.get(index) {
+            Some(win) => SimulationResult::Winner(WinningBoard {
+                last_number: win.number,
+                board: self.boards[win.board].clone(),
+                pattern: win.pattern.clone(),
+            }),
+            None => SimulationResult::Draw,
+        }
+    }
+}
+
+/// A set of cells that wins a board once every one of them is marked, in
+/// rows of `#` for cells that count and `.` for cells that don't.
+#[derive(Debug, PartialEq, Clone)]
+struct Mask {
+    rows : usize,
+    columns : usize,
+    cells : Vec<bool>,
+    /// How many cells count, so checking for a win doesn't recount them.
+    required : usize,
+}
+
+impl Mask {
+    fn size(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    fn required(&self) -> usize {
+        self.required
+    }
+}
+
+impl std::str::FromStr for Mask {
+    type Err = InputFileError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let rows = s.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.chars().map(|c| match c {
+                '#' => Ok(true),
+                '.' => Ok(false),
+                c => Err(InputFileError::GeneralError(format!("unexpected {} in mask", c))),
+            }).collect::<Result<Vec<bool>, _>>())
+            .collect::<Result<Vec<Vec<bool>>, _>>()?;
+        let columns = rows.first().map(Vec::len).unwrap_or_default();
+        if columns == 0 || rows.iter().any(|row| row.len() != columns) {
+            return Err(InputFileError::GeneralError("mask rows have to be the same, non-zero length".to_string()));
+        }
+        let cells : Vec<bool> = rows.iter().flatten().copied().collect();
+        Ok(Mask {
+            rows: rows.len(),
+            columns,
+            required: cells.iter().filter(|required| **required).count(),
+            cells,
+        })
     }
 }
 
+/// The ways a board can win. Diagonals only exist on square boards.
+#[derive(Debug, PartialEq, Clone)]
+enum WinPattern {
+    Rows,
+    Columns,
+    /// Either diagonal.
+    Diagonals,
+    FourCorners,
+    /// Both diagonals.
+    X,
+    Blackout,
+    Custom(Mask),
+}
+
+impl WinPattern {
+    fn defaults() -> Vec<WinPattern> {
+        vec![WinPattern::Rows, WinPattern::Columns]
+    }
+
+    fn is_complete(&self, board : &Board) -> bool {
+        match self {
+            WinPattern::Rows => board.is_winner_row(),
+            WinPattern::Columns => board.is_winner_column(),
+            WinPattern::Diagonals => board.is_winner_diagonal() || board.is_winner_anti_diagonal(),
+            WinPattern::FourCorners => board.corners().iter().all(|(x, y)| board.is_marked(*x, *y)),
+            WinPattern::X => board.is_winner_diagonal() && board.is_winner_anti_diagonal(),
+            WinPattern::Blackout => board.data.iter().all(|cell| cell.drawn),
+            WinPattern::Custom(mask) => mask.size() == board.size() && board.data.iter()
+                .zip(mask.cells.iter())
+                .all(|(cell, required)| cell.drawn || !required),
+        }
+    }
+}
+
+impl std::fmt::Display for WinPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WinPattern::Rows => write!(f, "rows"),
+            WinPattern::Columns => write!(f, "columns"),
+            WinPattern::Diagonals => write!(f, "diagonals"),
+            WinPattern::FourCorners => write!(f, "corners"),
+            WinPattern::X => write!(f, "x"),
+            WinPattern::Blackout => write!(f, "blackout"),
+            WinPattern::Custom(mask) => write!(f, "custom {}x{} mask", mask.rows, mask.columns),
+        }
+    }
+}
+
+/// Parses the name of a built in pattern, as shown by `Display`. Custom masks
+/// are parsed with `Mask::from_str`.
+impl std::str::FromStr for WinPattern {
+    type Err = InputFileError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "rows" => Ok(WinPattern::Rows),
+            "columns" => Ok(WinPattern::Columns),
+            "diagonals" => Ok(WinPattern::Diagonals),
+            "corners" => Ok(WinPattern::FourCorners),
+            "x" => Ok(WinPattern::X),
+            "blackout" => Ok(WinPattern::Blackout),
+            s => Err(InputFileError::GeneralError(format!("unknown win pattern {}", s))),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Copy, Clone, Default)]
 struct Cell {
@@ -103,19 +504,12 @@ impl Cell {
     }
 }
 
-#[derive(Debug, PartialEq, Default, Copy, Clone)]
+/// A bingo board of any size, stored row by row.
+#[derive(Debug, PartialEq, Default, Clone)]
 struct Board {
-    data : [[Cell; 5]; 5],
-}
-
-fn map_array<A : Copy, B, F>(data : [[A; 5]; 5], f : F) -> [[B; 5]; 5] where F: Fn(A) -> B{
-    [
-        [f(data[0][0]), f(data[0][1]), f(data[0][2]), f(data[0][3]), f(data[0][4])],
-        [f(data[1][0]), f(data[1][1]), f(data[1][2]), f(data[1][3]), f(data[1][4])],
-        [f(data[2][0]), f(data[2][1]), f(data[2][2]), f(data[2][3]), f(data[2][4])],
-        [f(data[3][0]), f(data[3][1]), f(data[3][2]), f(data[3][3]), f(data[3][4])],
-        [f(data[4][0]), f(data[4][1]), f(data[4][2]), f(data[4][3]), f(data[4][4])],
-    ]
+    rows : usize,
+    columns : usize,
+    data : Vec<Cell>,
 }
 
 #[derive(Debug)]
@@ -128,128 +522,839 @@ enum SimulationResult {
 struct WinningBoard {
     last_number : i64,
     board : Board,
+    pattern : WinPattern,
 }
 
 impl Board {
-    fn new(data : [[i64; 5]; 5]) -> Self {
-        Board {
-            data: map_array(data, Cell::new),
+    /// Builds a board from rows that all have the same length.
+    fn new<R : AsRef<[i64]>>(rows : impl IntoIterator<Item = R>) -> Self {
+        let mut board = Board::default();
+        for row in rows {
+            let row = row.as_ref();
+            debug_assert!(board.rows == 0 || row.len() == board.columns);
+            board.columns = row.len();
+            board.rows += 1;
+            board.data.extend(row.iter().copied().map(Cell::new));
         }
+        board
     }
 
+    fn size(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    fn cell(&self, x : usize, y : usize) -> &Cell {
+        &self.data[x * self.columns + y]
+    }
 
     fn is_marked(&self, x : usize, y : usize) -> bool {
-        self.data[x][y].drawn
+        self.cell(x, y).drawn
     }
 
-    fn number(&self, x : usize, y : usize) -> i64{
-        self.data[x][y].number
+    fn number(&self, x : usize, y : usize) -> i64 {
+        self.cell(x, y).number
     }
 
     fn draw_number(&mut self, num : i64) {
-        for x in 0..5 {
-            for y in 0..5 {
-                if self.number(x, y) == num {
-                    self.data[x][y].drawn = true
-                }
+        for cell in self.data.iter_mut() {
+            if cell.number == num {
+                cell.drawn = true
             }
         }
     }
 
     fn sum_of_all_unmarked_numbers(&self) -> i64 {
-        let mut sum = 0;
-        for x in 0..5 {
-            for y in 0..5 {
-                if !self.data[x][y].drawn {
-                    sum += self.data[x][y].number
-                }
-            }
-        }
-        sum
+        self.data.iter()
+            .filter(|cell| !cell.drawn)
+            .map(|cell| cell.number)
+            .sum()
     }
 
     fn is_winner_row(&self) -> bool {
-        for x in 0..5 {
-            let mut count = 0;
-            for y in 0..5 {
-                if self.is_marked(x, y) {
-                    count += 1
+        (0..self.rows).any(|x| (0..self.columns).all(|y| self.is_marked(x, y)))
+    }
+
+    fn is_winner_column(&self) -> bool {
+        (0..self.columns).any(|y| (0..self.rows).all(|x| self.is_marked(x, y)))
+    }
+
+    fn is_winner_diagonal(&self) -> bool {
+        self.rows == self.columns && (0..self.rows).all(|i| self.is_marked(i, i))
+    }
+
+    fn is_winner_anti_diagonal(&self) -> bool {
+        self.rows == self.columns && (0..self.rows).all(|i| self.is_marked(i, self.columns - i - 1))
+    }
+
+    fn corners(&self) -> [(usize, usize); 4] {
+        [(0, 0), (0, self.columns - 1), (self.rows - 1, 0), (self.rows - 1, self.columns - 1)]
+    }
+
+    /// Narrow boards share corners, a single cell is all four of them.
+    fn distinct_corners(&self) -> usize {
+        (if self.rows > 1 { 2 } else { 1 }) * (if self.columns > 1 { 2 } else { 1 })
+    }
+
+    /// Marks a single cell, returning whether it wasn't marked yet.
+    fn mark(&mut self, x : usize, y : usize) -> bool {
+        let cell = &mut self.data[x * self.columns + y];
+        !std::mem::replace(&mut cell.drawn, true)
+    }
+
+    /// The first of `patterns` that this board has completed.
+    fn winning_pattern<'a>(&self, patterns : &'a [WinPattern]) -> Option<&'a WinPattern> {
+        patterns.iter().find(|pattern| pattern.is_complete(self))
+    }
+
+}
+
+/// Problems with the boards in the input. Boards are numbered from one in the
+/// order they appear, rows from one within their board.
+#[derive(Error, Debug, PartialEq)]
+enum BoardError {
+    #[error("missing numbers to draw")]
+    MissingNumbers,
+    #[error("missing round count, expected rounds N")]
+    MissingRounds,
+    #[error("missing numbers to draw in round {round}")]
+    MissingRound { round : usize },
+    #[error("board {board}: row {row} has {found} numbers, expected {expected}")]
+    RowWidth { board : usize, row : usize, found : usize, expected : usize },
+    #[error("board {board} is {rows}x{columns}, expected {expected_rows}x{expected_columns} like board 1")]
+    Size { board : usize, rows : usize, columns : usize, expected_rows : usize, expected_columns : usize },
+    #[error("mask is {rows}x{columns}, expected {expected_rows}x{expected_columns} like the boards")]
+    MaskSize { rows : usize, columns : usize, expected_rows : usize, expected_columns : usize },
+}
+
+impl From<BoardError> for InputFileError {
+    fn from(error : BoardError) -> Self {
+        InputFileError::GeneralError(error.to_string())
+    }
+}
+
+/// Custom masks have to be the size of the boards, which all share the size
+/// of the first one.
+fn check_masks(boards : &[Board], patterns : &[WinPattern]) -> Result<(), BoardError> {
+    if let Some(board) = boards.first() {
+        for pattern in patterns.iter() {
+            if let WinPattern::Custom(mask) = pattern {
+                if mask.size() != board.size() {
+                    return Err(BoardError::MaskSize {
+                        rows: mask.rows,
+                        columns: mask.columns,
+                        expected_rows: board.rows,
+                        expected_columns: board.columns,
+                    });
                 }
             }
-            if count == 5 {
-                return true
+        }
+    }
+    Ok(())
+}
+
+/// Parses a section holding a single comma separated line of numbers to draw.
+fn parse_draws(section : &InputFile) -> Option<Result<Vec<i64>, InputFileError>> {
+    match section.lines().as_slice() {
+        [line] => Some(line.split(",")
+            .map(|i| i.trim().parse::<i64>())
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(InputFileError::from)),
+        _ => None,
+    }
+}
+
+/// Parses one board per section. The first board decides the size of every
+/// other board.
+fn parse_boards<I : Iterator<Item = InputFile>>(sections : I) -> Result<Vec<Board>, InputFileError> {
+    let mut boards : Vec<Board> = vec![];
+    for (i, section) in sections.enumerate() {
+        let board = i + 1;
+        let rows = section.lines().iter()
+            .map(|line| line.split_whitespace().map(|i| i.parse::<i64>()).collect::<Result<Vec<i64>, _>>())
+            .collect::<Result<Vec<Vec<i64>>, _>>()?;
+        let expected = rows[0].len();
+        if let Some((row, found)) = rows.iter().map(Vec::len).enumerate().find(|(_, found)| *found != expected) {
+            return Err(BoardError::RowWidth {
+                board,
+                row: row + 1,
+                found,
+                expected,
+            }.into());
+        }
+        let parsed = Board::new(rows);
+        if let Some(first) = boards.first() {
+            if first.size() != parsed.size() {
+                return Err(BoardError::Size {
+                    board,
+                    rows: parsed.rows,
+                    columns: parsed.columns,
+                    expected_rows: first.rows,
+                    expected_columns: first.columns,
+                }.into());
             }
         }
-        false
+        boards.push(parsed);
     }
+    Ok(boards)
+}
 
-    fn is_winner_column(&self) -> bool {
-        for y in 0..5 {
-            let mut count = 0;
-            for x in 0..5 {
-                if self.is_marked(x, y) {
-                    count += 1
-                }
+/// The numbers to draw come first, then every board, each separated by a
+/// blank line.
+impl TryFrom<InputSections> for Game {
+    type Error = InputFileError;
+
+    fn try_from(input : InputSections) -> Result<Self, Self::Error> {
+        let mut sections = input.sections.into_iter();
+        let to_draw = match sections.next().as_ref().and_then(parse_draws) {
+            Some(to_draw) => to_draw?,
+            None => return Err(BoardError::MissingNumbers.into()),
+        };
+
+        Ok(Game {
+            to_draw,
+            boards: parse_boards(sections)?,
+            patterns: WinPattern::defaults(),
+        })
+    }
+}
+
+/// Which boards drop out of a tournament after each round. A round never
+/// eliminates every board that is left.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Elimination {
+    Keep,
+    /// Boards that didn't win at all in the round.
+    NonWinners,
+    /// The boards with the lowest total score, later boards first on ties.
+    Lowest(usize),
+}
+
+impl std::str::FromStr for Elimination {
+    type Err = InputFileError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "keep" => Ok(Elimination::Keep),
+            None if s == "nonwinners" => Ok(Elimination::NonWinners),
+            Some(("lowest", count)) => Ok(Elimination::Lowest(count.parse()?)),
+            _ => Err(InputFileError::GeneralError(format!("unknown elimination {}", s))),
+        }
+    }
+}
+
+/// What happened to the boards that played a round. Boards that didn't win
+/// score nothing.
+#[derive(Debug, PartialEq)]
+struct Round {
+    scores : Vec<(usize, i64)>,
+    winner : Option<usize>,
+    eliminated : Vec<usize>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct Standing {
+    board : usize,
+    total : i64,
+    rounds_won : usize,
+    /// The round the board was eliminated in, counting from one.
+    eliminated : Option<usize>,
+}
+
+impl std::fmt::Display for Standing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "board {} scored {} winning {} rounds", self.board, self.total, self.rounds_won)?;
+        if let Some(round) = self.eliminated {
+            write!(f, ", eliminated in round {}", round)?;
+        }
+        Ok(())
+    }
+}
+
+/// Several rounds played with the same boards, each round starting from
+/// clean boards and adding `sum_of_all_unmarked_numbers() * last_number` of
+/// every winning board to its total.
+struct Tournament {
+    rounds : Vec<Vec<i64>>,
+    boards : Vec<Board>,
+    patterns : Vec<WinPattern>,
+    elimination : Elimination,
+}
+
+impl Tournament {
+    /// Replaces the patterns that win a board, the same way as
+    /// `Game::with_patterns`.
+    fn with_patterns(&mut self, patterns : Vec<WinPattern>) -> Result<(), BoardError> {
+        check_masks(&self.boards, &patterns)?;
+        self.patterns = patterns;
+        Ok(())
+    }
+
+    /// Plays every round, returning what happened in each of them and the
+    /// leaderboard at the end.
+    fn play(&self) -> (Vec<Round>, Vec<Standing>) {
+        let mut standings : Vec<Standing> = (0..self.boards.len())
+            .map(|board| Standing {
+                board,
+                total: 0,
+                rounds_won: 0,
+                eliminated: None,
+            })
+            .collect();
+        let mut rounds = vec![];
+        for (round, to_draw) in self.rounds.iter().enumerate() {
+            let active : Vec<usize> = standings.iter().filter(|standing| standing.eliminated.is_none()).map(|standing| standing.board).collect();
+            let game = Game {
+                to_draw: to_draw.clone(),
+                boards: active.iter().map(|board| self.boards[*board].clone()).collect(),
+                patterns: self.patterns.clone(),
+            };
+            let timeline = game.indexed_timeline();
+
+            let mut scores : Vec<(usize, i64)> = active.iter().map(|board| (*board, 0)).collect();
+            for win in timeline.wins.iter() {
+                scores[win.board].1 = win.score;
+                standings[active[win.board]].total += win.score;
             }
-            if count == 5 {
-                return true
+            let winner = timeline.wins.first().map(|win| active[win.board]);
+            if let Some(winner) = winner {
+                standings[winner].rounds_won += 1;
+            }
+
+            let mut eliminated : Vec<usize> = match self.elimination {
+                Elimination::Keep => vec![],
+                Elimination::NonWinners => timeline.never_won.iter().map(|board| active[*board]).collect(),
+                Elimination::Lowest(count) => {
+                    let mut lowest = active.clone();
+                    lowest.sort_by_key(|board| (standings[*board].total, std::cmp::Reverse(*board)));
+                    lowest.truncate(count);
+                    lowest
+                },
+            };
+            if eliminated.len() >= active.len() {
+                eliminated.clear();
             }
+            eliminated.sort_unstable();
+            for board in eliminated.iter() {
+                standings[*board].eliminated = Some(round + 1);
+            }
+
+            rounds.push(Round {
+                scores,
+                winner,
+                eliminated,
+            });
         }
-        false
+
+        // boards still in the tournament rank ahead of eliminated ones, which
+        // rank by how long they lasted
+        standings.sort_by_key(|standing| (std::cmp::Reverse(standing.eliminated.unwrap_or(usize::MAX)), std::cmp::Reverse(standing.total), standing.board));
+        (rounds, standings)
     }
+}
+
+/// A `rounds N` line first, then the numbers to draw in each of the `N`
+/// rounds, one comma separated line each, then the boards, all separated by
+/// blank lines.
+impl TryFrom<InputSections> for Tournament {
+    type Error = InputFileError;
+
+    fn try_from(input : InputSections) -> Result<Self, Self::Error> {
+        let mut sections = input.sections.into_iter();
+        let count = match sections.next().map(|section| section.lines()).as_deref() {
+            Some([header]) => match header.strip_prefix("rounds") {
+                Some(count) => count.trim().parse::<usize>()?,
+                None => return Err(BoardError::MissingRounds.into()),
+            },
+            _ => return Err(BoardError::MissingRounds.into()),
+        };
+        if count == 0 {
+            return Err(BoardError::MissingNumbers.into());
+        }
+        let mut rounds = vec![];
+        for round in 1..=count {
+            match sections.next().as_ref().and_then(parse_draws) {
+                Some(to_draw) => rounds.push(to_draw?),
+                None => return Err(BoardError::MissingRound { round }.into()),
+            }
+        }
 
-    fn is_winner(&self) -> bool {
-        self.is_winner_column() || self.is_winner_row()
+        Ok(Tournament {
+            rounds,
+            boards: parse_boards(sections)?,
+            patterns: WinPattern::defaults(),
+            elimination: Elimination::Keep,
+        })
     }
+}
 
+/// Boards that can't be built for a draw sequence. Draws count from zero.
+#[derive(Error, Debug, PartialEq)]
+enum SolveError {
+    #[error("a board needs {needed} distinct numbers, the pool only has {available}")]
+    PoolTooSmall { needed : usize, available : usize },
+    #[error("a board that never wins needs {needed} numbers that are never drawn, the pool only has {available}")]
+    NotEnoughUndrawn { needed : usize, available : usize },
+    #[error("there is no draw {draw}, only {draws} numbers are drawn")]
+    DrawOutOfRange { draw : usize, draws : usize },
+    #[error("draw {draw} repeats {number}, so it can't complete a board")]
+    RepeatedNumber { draw : usize, number : i64 },
+    #[error("draw {draw} is too early, a line needs {needed} numbers drawn before it")]
+    TooEarly { draw : usize, needed : usize },
+    #[error("no board wins at draw {draw} with a score of {score}")]
+    Score { draw : usize, score : i64 },
+    #[error("scores can only be solved for pools without negative numbers")]
+    NegativeNumbers,
+    #[error("no board wins at draw {draw} with a score of {score}, the most it can score is {max}")]
+    ScoreTooHigh { draw : usize, score : i64, max : i64 },
+    #[error("a sum of {sum} needs a table of more than {max} cells")]
+    TableTooLarge { sum : i64, max : usize },
 }
 
-#[derive(Debug)]
-enum ParsingState {
-    Beginning,
-    Board(usize),
+/// Builds boards that behave in a chosen way for a draw sequence, using
+/// distinct numbers from a pool and winning with rows and columns.
+struct BoardSolver<'a> {
+    to_draw : &'a [i64],
+    /// Sorted and without duplicates.
+    pool : Vec<i64>,
+    rows : usize,
+    columns : usize,
 }
 
-impl TryFrom<InputFile> for Game {
-    type Error = InputFileError;
+impl<'a> BoardSolver<'a> {
+    /// A solver drawing its numbers from the numbers that are drawn.
+    fn new(to_draw : &'a [i64], rows : usize, columns : usize) -> Self {
+        BoardSolver {
+            to_draw,
+            pool: vec![],
+            rows,
+            columns,
+        }.with_pool(to_draw.to_vec())
+    }
 
-    fn try_from(input : InputFile) -> Result<Self, Self::Error> {
-        let mut game = Game::default();
+    fn with_pool(mut self, mut pool : Vec<i64>) -> Self {
+        pool.sort_unstable();
+        pool.dedup();
+        self.pool = pool;
+        self
+    }
 
-        let mut state = ParsingState::Beginning;
+    fn first_draw(&self, number : i64) -> Option<usize> {
+        self.to_draw.iter().position(|drawn| *drawn == number)
+    }
 
-        let mut board : [[i64; 5]; 5] = [[0; 5]; 5];
+    /// Pool numbers in the order they are first drawn, up to draw `end`.
+    fn drawn_before(&self, end : usize) -> Vec<i64> {
+        let mut drawn = vec![];
+        for (draw, number) in self.to_draw.iter().enumerate().take(end) {
+            if self.first_draw(*number) == Some(draw) && self.pool.binary_search(number).is_ok() {
+                drawn.push(*number);
+            }
+        }
+        drawn
+    }
+
+    /// Pool numbers that aren't drawn by draw `draw`.
+    fn undrawn_at(&self, draw : usize) -> Vec<i64> {
+        self.pool.iter()
+            .filter(|number| self.first_draw(**number).is_none_or(|first| first > draw))
+            .copied()
+            .collect()
+    }
 
-        for line in input.lines() {
-            //println!("parsing state={:?} line=[{}]", state, line);
-            match state {
-                ParsingState::Beginning => {
-                    game.to_draw = line.split(",").map(|i| i.parse::<i64>()).collect::<Result<Vec<i64>, _>>()?;
-                    state = ParsingState::Board(0);
+    fn check_pool(&self) -> Result<(), SolveError> {
+        if self.pool.len() < self.rows * self.columns {
+            return Err(SolveError::PoolTooSmall {
+                needed: self.rows * self.columns,
+                available: self.pool.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fills every cell `place` left empty with unused pool numbers.
+    fn board(&self, mut place : HashMap<(usize, usize), i64>) -> Board {
+        let used : Vec<i64> = place.values().copied().collect();
+        let mut unused = self.pool.iter().filter(|number| !used.contains(number));
+        Board::new((0..self.rows)
+            .map(|x| (0..self.columns)
+                .map(|y| place.remove(&(x, y)).or_else(|| unused.next().copied()).unwrap_or_default())
+                .collect::<Vec<i64>>()))
+    }
+
+    /// Plays the draws against `board` on its own, the way it would play in a
+    /// game.
+    fn play(&self, board : &Board) -> Timeline {
+        Game {
+            to_draw: self.to_draw.to_vec(),
+            boards: vec![board.clone()],
+            patterns: WinPattern::defaults(),
+        }.timeline()
+    }
+
+    /// No board can win before its shortest line could be filled, so this one
+    /// puts the first numbers drawn in such a line.
+    fn earliest_winner(&self) -> Result<Board, SolveError> {
+        self.check_pool()?;
+        let length = self.rows.min(self.columns);
+        let drawn = self.drawn_before(self.to_draw.len());
+        if drawn.len() < length {
+            return Err(SolveError::TooEarly {
+                draw: self.to_draw.len(),
+                needed: length,
+            });
+        }
+        let place = drawn.into_iter()
+            .take(length)
+            .enumerate()
+            .map(|(i, number)| if self.columns <= self.rows { ((0, i), number) } else { ((i, 0), number) })
+            .collect();
+        Ok(self.board(place))
+    }
+
+    /// Keeps every row and column from filling up with a number that is never
+    /// drawn in each of them.
+    fn never_winner(&self) -> Result<Board, SolveError> {
+        self.check_pool()?;
+        let needed = self.rows.max(self.columns);
+        let undrawn = self.undrawn_at(self.to_draw.len());
+        if undrawn.len() < needed {
+            return Err(SolveError::NotEnoughUndrawn {
+                needed,
+                available: undrawn.len(),
+            });
+        }
+        let place = undrawn.into_iter()
+            .take(needed)
+            .enumerate()
+            .map(|(i, number)| ((i % self.rows, i % self.columns), number))
+            .collect();
+        Ok(self.board(place))
+    }
+
+    /// A board completing its first row on draw `draw` and scoring `score`.
+    /// The rest of the board is made of numbers drawn earlier, which don't
+    /// count towards the score, and numbers drawn later, which do and of which
+    /// every other row and column needs at least one so it doesn't win first.
+    fn winner_at(&self, draw : usize, score : i64) -> Result<Board, SolveError> {
+        if self.rows == 1 && self.columns > 1 {
+            // a single row wins as soon as any of its cells is drawn, so
+            // solve for a single column instead
+            let transposed = BoardSolver {
+                to_draw: self.to_draw,
+                pool: self.pool.clone(),
+                rows: self.columns,
+                columns: self.rows,
+            };
+            let board = transposed.winner_at(draw, score)?;
+            return Ok(Board::new([(0..board.rows).map(|x| board.number(x, 0)).collect::<Vec<i64>>()]));
+        }
+
+        self.check_pool()?;
+        let number = match self.to_draw.get(draw) {
+            Some(number) => *number,
+            None => return Err(SolveError::DrawOutOfRange {
+                draw,
+                draws: self.to_draw.len(),
+            }),
+        };
+        if self.first_draw(number) != Some(draw) || self.pool.binary_search(&number).is_err() {
+            return Err(SolveError::RepeatedNumber {
+                draw,
+                number,
+            });
+        }
+        if self.pool.iter().any(|number| *number < 0) {
+            return Err(SolveError::NegativeNumbers);
+        }
+
+        let (others, line) = (self.rows - 1, self.columns - 1);
+        let early = self.drawn_before(draw);
+        if early.len() < line {
+            return Err(SolveError::TooEarly {
+                draw,
+                needed: line,
+            });
+        }
+        let late = self.undrawn_at(draw);
+
+        let cells = others * self.columns;
+        let min_late = if others == 0 { 0 } else { others.max(line) };
+        let min_late = min_late.max(cells.saturating_sub(early.len() - line));
+        let max_late = cells.min(late.len());
+        // `late` is sorted, so this is the largest sum of unmarked numbers,
+        // checked before the subset sum table is sized by the score
+        let reachable : i64 = late.iter().rev().take(max_late).sum();
+        let error = SolveError::Score {
+            draw,
+            score,
+        };
+        let chosen = match (number, score) {
+            _ if min_late > max_late => return Err(error),
+            (0, 0) => late[..min_late].to_vec(),
+            (0, _) => return Err(error),
+            (number, score) if score % number == 0 && score / number > reachable => return Err(SolveError::ScoreTooHigh {
+                draw,
+                score,
+                max: number * reachable,
+            }),
+            (number, score) if score % number == 0 => subset_with_sum(&late, min_late..=max_late, score / number)?.ok_or(error)?,
+            _ => return Err(error),
+        };
+
+        let mut place = HashMap::new();
+        place.insert((0, 0), number);
+        for (y, number) in early.iter().take(line).enumerate() {
+            place.insert((0, y + 1), *number);
+        }
+        // one late number in every other row and column, then whatever is left
+        let mut free : Vec<(usize, usize)> = (0..min_late.min(others.max(line)))
+            .map(|i| if line == 0 { (i + 1, 0) } else { (1 + i % others, 1 + i % line) })
+            .collect();
+        free.extend((1..self.rows).flat_map(|x| (0..self.columns).map(move |y| (x, y))).filter(|cell| !free.contains(cell)).collect::<Vec<_>>());
+        let fillers = chosen.iter().chain(early.iter().skip(line));
+        for (cell, number) in free.into_iter().zip(fillers) {
+            place.insert(cell, *number);
+        }
+        Ok(self.board(place))
+    }
+}
+
+/// The most cells `subset_with_sum` allocates for its table, which has a row
+/// for every number of picks and a column for every total up to the sum.
+const SUBSET_TABLE_CELLS : usize = 1 << 22;
+
+/// Picks `count` of `numbers`, for any count in `counts`, adding up to `sum`.
+/// Tracks which sums every number of picks can reach, the same way as the
+/// classic subset sum table.
+fn subset_with_sum(numbers : &[i64], counts : std::ops::RangeInclusive<usize>, sum : i64) -> Result<Option<Vec<i64>>, SolveError> {
+    if numbers.iter().any(|number| *number < 0) {
+        return Err(SolveError::NegativeNumbers);
+    }
+    let max_count = *counts.end();
+    let reachable : i64 = {
+        let mut sorted = numbers.to_vec();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        sorted.iter().take(max_count).sum()
+    };
+    if sum < 0 || sum > reachable || counts.is_empty() {
+        return Ok(None);
+    }
+    let cells = (sum as usize).checked_add(1).and_then(|columns| columns.checked_mul(max_count + 1));
+    if cells.is_none_or(|cells| cells > SUBSET_TABLE_CELLS) {
+        return Err(SolveError::TableTooLarge {
+            sum,
+            max: SUBSET_TABLE_CELLS,
+        });
+    }
+    let sum = sum as usize;
+    // picked[count][total] is the number that first reached the total
+    let mut picked : Vec<Vec<Option<usize>>> = vec![vec![None; sum + 1]; max_count + 1];
+    let mut reached = vec![vec![false; sum + 1]; max_count + 1];
+    reached[0][0] = true;
+    for (i, number) in numbers.iter().enumerate() {
+        let number = *number as usize;
+        for count in (1..=max_count).rev() {
+            for total in (number..=sum).rev() {
+                if reached[count - 1][total - number] && !reached[count][total] {
+                    reached[count][total] = true;
+                    picked[count][total] = Some(i);
                 }
-                ParsingState::Board(row) => {
-                    let cells = line.split_whitespace().map(|i| i.trim().parse::<i64>()).collect::<Result<Vec<i64>, _>>()?;
-                    for (place, element) in board[row].iter_mut().zip(cells.iter()) {
-                        *place = *element;
+            }
+        }
+    }
+
+    let Some(mut count) = counts.into_iter().find(|count| reached[*count][sum]) else {
+        return Ok(None);
+    };
+    let mut total = sum;
+    let mut subset = vec![];
+    while count > 0 {
+        let Some(i) = picked[count][total] else {
+            return Ok(None);
+        };
+        subset.push(numbers[i]);
+        total -= numbers[i] as usize;
+        count -= 1;
+    }
+    Ok(Some(subset))
+}
+
+impl std::fmt::Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for x in 0..self.rows {
+            let row = (0..self.columns)
+                .map(|y| format!("{:>2}", self.number(x, y)))
+                .collect::<Vec<String>>();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Odds of every board when the numbers are drawn in a random order.
+mod odds {
+    use super::{Board, WinPattern};
+    use aoc2021::Rng;
+    use thiserror::Error;
+
+    #[derive(Error, Debug, PartialEq)]
+    pub enum OddsError {
+        #[error("exact odds need a pool of at most {max} numbers, not {size}")]
+        PoolTooLarge { size : usize, max : usize },
+    }
+
+    /// How a board fares against random draws. Boards winning on the same
+    /// draw share that win equally.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct Odds {
+        pub first : f64,
+        /// `None` for boards the pool can't complete.
+        pub expected_draws : Option<f64>,
+    }
+
+    impl std::fmt::Display for Odds {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self.expected_draws {
+                Some(draws) => write!(f, "wins first {:.4}, wins after {:.2} draws", self.first, draws),
+                None => write!(f, "wins first {:.4}, never wins", self.first),
+            }
+        }
+    }
+
+    fn distinct(pool : &[i64]) -> Vec<i64> {
+        let mut pool = pool.to_vec();
+        pool.sort_unstable();
+        pool.dedup();
+        pool
+    }
+
+    /// Plays `trials` games drawing every number of `pool` in a random order.
+    pub fn monte_carlo(boards : &[Board], patterns : &[WinPattern], pool : &[i64], trials : usize, rng : &mut Rng) -> Vec<Odds> {
+        let mut pool = distinct(pool);
+        let mut first = vec![0.0; boards.len()];
+        let mut draws = vec![0; boards.len()];
+        let mut always_won = vec![true; boards.len()];
+        for _ in 0..trials {
+            rng.shuffle(&mut pool);
+            let mut boards = boards.to_vec();
+            let mut won_at : Vec<Option<usize>> = vec![None; boards.len()];
+            for (draw, number) in pool.iter().enumerate() {
+                for (board, won) in boards.iter_mut().zip(won_at.iter_mut()) {
+                    if won.is_none() {
+                        board.draw_number(*number);
+                        if board.winning_pattern(patterns).is_some() {
+                            *won = Some(draw + 1);
+                        }
                     }
-                    if row == 4 {
-                        state = ParsingState::Board(0);
-                        game.boards.push(Board::new(board));
-                    } else {
-                        state = ParsingState::Board(row+ 1);
+                }
+                if won_at.iter().all(Option::is_some) {
+                    break;
+                }
+            }
+
+            let earliest = won_at.iter().flatten().min().copied();
+            let winners = won_at.iter().filter(|won| won.is_some() && **won == earliest).count();
+            for (i, won) in won_at.iter().enumerate() {
+                match won {
+                    Some(draw) => {
+                        draws[i] += draw;
+                        if *won == earliest {
+                            first[i] += 1.0 / winners as f64;
+                        }
+                    },
+                    None => always_won[i] = false,
+                }
+            }
+        }
+        (0..boards.len())
+            .map(|i| Odds {
+                first: first[i] / trials as f64,
+                expected_draws: if always_won[i] && trials > 0 { Some(draws[i] as f64 / trials as f64) } else { None },
+            })
+            .collect()
+    }
+
+    /// The largest pool `exact` works through, one entry per subset.
+    pub const MAX_EXACT_POOL : usize = 16;
+
+    fn choose(n : usize, k : usize) -> f64 {
+        (0..k).fold(1.0, |total, i| total * (n - i) as f64 / (i + 1) as f64)
+    }
+
+    /// Whether a board wins only depends on which numbers were drawn, and the
+    /// first `t` numbers of a random order are a random subset of size `t`.
+    /// That turns every order of the pool into every subset of it.
+    pub fn exact(boards : &[Board], patterns : &[WinPattern], pool : &[i64]) -> Result<Vec<Odds>, OddsError> {
+        let pool = distinct(pool);
+        let n = pool.len();
+        if n > MAX_EXACT_POOL {
+            return Err(OddsError::PoolTooLarge {
+                size: n,
+                max: MAX_EXACT_POOL,
+            });
+        }
+
+        // complete[b][drawn] for every subset of the pool
+        let complete : Vec<Vec<bool>> = boards.iter()
+            .map(|board| (0..1_usize << n)
+                .map(|drawn| {
+                    let mut board = board.clone();
+                    for (i, number) in pool.iter().enumerate() {
+                        if drawn & (1 << i) != 0 {
+                            board.draw_number(*number);
+                        }
                     }
+                    board.winning_pattern(patterns).is_some()
+                })
+                .collect())
+            .collect();
+
+        let mut first = vec![0.0; boards.len()];
+        for drawn in 0..1_usize << n {
+            if complete.iter().any(|complete| complete[drawn]) {
+                continue;
+            }
+            let size = drawn.count_ones() as usize;
+            // chance of drawing exactly these first, then any one other number
+            let chance = 1.0 / (choose(n, size) * (n - size) as f64);
+            for next in (0..n).filter(|i| drawn & (1 << i) == 0) {
+                let winners : Vec<usize> = (0..boards.len()).filter(|b| complete[*b][drawn | 1 << next]).collect();
+                for winner in winners.iter() {
+                    first[*winner] += chance / winners.len() as f64;
                 }
             }
         }
 
-        Ok(game)
+        Ok((0..boards.len())
+            .map(|b| {
+                let mut not_won = vec![0.0; n];
+                for drawn in (0..1_usize << n).filter(|drawn| !complete[b][*drawn]) {
+                    let size = drawn.count_ones() as usize;
+                    if size < n {
+                        not_won[size] += 1.0;
+                    }
+                }
+                Odds {
+                    first: first[b],
+                    // the sum of the chances of still not having won after
+                    // every draw
+                    expected_draws: complete[b][(1 << n) - 1]
+                        .then(|| (0..n).map(|size| not_won[size] / choose(n, size)).sum()),
+                }
+            })
+            .collect())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use aoc2021::load_sample;
+    use rstest::rstest;
+    use aoc2021::{load_sample_sections, load_input_sections};
+
+    fn is_winner(board : &Board) -> bool {
+        board.winning_pattern(&WinPattern::defaults()).is_some()
+    }
 
     #[test]
     fn new_board() {
@@ -262,15 +1367,19 @@ mod test {
         ];
         let board = Board::new(data);
 
-        assert_eq!(map_array(board.data, |c| c.number), data);
+        for (x, row) in data.iter().enumerate() {
+            for (y, number) in row.iter().enumerate() {
+                assert_eq!(board.number(x, y), *number);
+            }
+        }
 
         for x in 0..5 {
             for y in 0..5 {
-                assert_eq!(board.is_marked(x, y), false);
+                assert!(!board.is_marked(x, y));
             }
         }
 
-        assert_eq!(board.is_winner(), false)
+        assert!(!is_winner(&board))
     }
 
     #[test]
@@ -284,9 +1393,9 @@ mod test {
         ]);
         for x in 0..5 {
             for y in 0..5 {
-                assert_eq!(board.is_marked(x, y), false);
+                assert!(!board.is_marked(x, y));
                 board.draw_number(board.number(x, y));
-                assert_eq!(board.is_marked(x, y), true);
+                assert!(board.is_marked(x, y));
             }
         }
     }
@@ -301,15 +1410,15 @@ mod test {
             [ 1, 12, 20, 15, 19],
         ]);
         board.draw_number(6);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(10);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(3);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(18);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(5);
-        assert_eq!(board.is_winner(), true);
+        assert!(is_winner(&board));
     }
 
     #[test]
@@ -322,20 +1431,20 @@ mod test {
             [ 1, 12, 20, 15, 19],
         ]);
         board.draw_number(11);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(4);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(16);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(18);
-        assert_eq!(board.is_winner(), false);
+        assert!(!is_winner(&board));
         board.draw_number(15);
-        assert_eq!(board.is_winner(), true);
+        assert!(is_winner(&board));
     }
 
     #[test]
     fn parse_sample() -> anyhow::Result<()> {
-        let game : Game = load_sample(4)?;
+        let game : Game = load_sample_sections(4)?;
         assert_eq!(game.to_draw, vec![7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1]);
         assert_eq!(game.boards, vec![
             Board::new([
@@ -366,13 +1475,14 @@ mod test {
 
     #[test]
     fn simulate() -> anyhow::Result<()> {
-        let mut game : Game = load_sample(4)?;
-        let result = game.simulate();
+        let game : Game = load_sample_sections(4)?;
+        let result = game.timeline().first_winner();
 
         match result {
             SimulationResult::Winner(WinningBoard {
                 board,
                 last_number,
+                ..
             }) => {
                 assert_eq!(board.sum_of_all_unmarked_numbers(), 188);
                 assert_eq!(last_number, 24);
@@ -385,13 +1495,14 @@ mod test {
 
     #[test]
     fn find_last_board_to_win() -> anyhow::Result<()> {
-        let mut game : Game = load_sample(4)?;
-        let result = game.find_last_board_to_win();
+        let game : Game = load_sample_sections(4)?;
+        let result = game.timeline().last_winner();
 
         match result {
             SimulationResult::Winner(WinningBoard {
                 board,
                 last_number,
+                ..
             }) => {
                 assert_eq!(board.sum_of_all_unmarked_numbers(), 148);
                 assert_eq!(last_number, 13);
@@ -404,13 +1515,14 @@ mod test {
 
     #[test]
     fn part1() -> anyhow::Result<()> {
-        let mut game : Game = load_input(4)?;
-        let result = game.simulate();
+        let game : Game = load_input_sections(4)?;
+        let result = game.timeline().first_winner();
 
         match result {
             SimulationResult::Winner(WinningBoard {
                 board,
                 last_number,
+                ..
             }) => {
                 assert_eq!(board.sum_of_all_unmarked_numbers() * last_number, 72770);
             },
@@ -419,4 +1531,542 @@ mod test {
 
         Ok(())
     }
+
+    fn sections(input : &str) -> InputSections {
+        InputSections::new(input.to_string())
+    }
+
+    #[test]
+    fn rectangular_boards() -> anyhow::Result<()> {
+        let game : Game = sections("
+            1,2,3,4,5,6
+
+            1 9 9
+            9 2 9
+
+            9 9 4
+            9 9 5
+            ").try_into()?;
+        assert_eq!(game.boards[0].size(), (2, 3));
+        assert_eq!(game.boards[1], Board::new([[9, 9, 4], [9, 9, 5]]));
+
+        match game.timeline().first_winner() {
+            SimulationResult::Winner(WinningBoard {
+                board,
+                last_number,
+                ..
+            }) => {
+                // the second board wins on its last column
+                assert_eq!(last_number, 5);
+                assert_eq!(board.sum_of_all_unmarked_numbers(), 36);
+            },
+            _ => return Err(anyhow::anyhow!("invalid simulation result")),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn single_row_board() -> anyhow::Result<()> {
+        // every column of a single row is complete as soon as it is drawn
+        let game : Game = sections("3,1,2\n\n1 2 3").try_into()?;
+        assert!(matches!(game.timeline().first_winner(), SimulationResult::Winner(WinningBoard { last_number: 3, .. })));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("", "General Error: missing numbers to draw")]
+    #[case("1,2\n3,4\n\n1 2\n3 4", "General Error: missing numbers to draw")]
+    #[case("1,2\n\n1 2\n3 4\n\n1 2\n3\n", "General Error: board 2: row 2 has 1 numbers, expected 2")]
+    #[case("1,2\n\n1 2\n3 4\n\n1 2 5\n3 4 6\n", "General Error: board 2 is 2x3, expected 2x2 like board 1")]
+    #[case("1,2\n\n1 2\n3 4\n\n1 2\n3 4\n\n1 2\n", "General Error: board 3 is 1x2, expected 2x2 like board 1")]
+    fn malformed_boards(#[case] input : &str, #[case] message : &str) {
+        let game : Result<Game, InputFileError> = sections(input).try_into();
+        match game {
+            Err(error) => assert_eq!(error.to_string(), message),
+            Ok(_) => panic!("expected {}", message),
+        }
+    }
+
+    fn sample_board() -> Board {
+        Board::new([
+            [22, 13, 17, 11,  0],
+            [ 8,  2, 23,  4, 24],
+            [21,  9, 14, 16,  7],
+            [ 6, 10,  3, 18,  5],
+            [ 1, 12, 20, 15, 19],
+        ])
+    }
+
+    fn plus() -> anyhow::Result<WinPattern> {
+        Ok(WinPattern::Custom("
+            .....
+            ..#..
+            .###.
+            ..#..
+            .....
+        ".parse()?))
+    }
+
+    #[rstest]
+    #[case(vec![22, 2, 14, 18, 19], WinPattern::Diagonals)]
+    #[case(vec![0, 4, 14, 10, 1], WinPattern::Diagonals)]
+    #[case(vec![22, 0, 1, 19], WinPattern::FourCorners)]
+    #[case(vec![22, 2, 14, 18, 19, 0, 4, 10, 1], WinPattern::X)]
+    #[case((0..25).collect(), WinPattern::Blackout)]
+    #[case(vec![23, 9, 14, 16, 3], plus().unwrap())]
+    fn win_patterns(#[case] numbers : Vec<i64>, #[case] pattern : WinPattern) {
+        let mut board = sample_board();
+        let patterns = vec![pattern.clone()];
+        for (i, number) in numbers.iter().enumerate() {
+            assert_eq!(board.winning_pattern(&patterns), None, "won before drawing {}", numbers[i]);
+            board.draw_number(*number);
+        }
+        assert_eq!(board.winning_pattern(&patterns), Some(&pattern));
+    }
+
+    #[test]
+    fn diagonals_need_square_boards() {
+        let mut board = Board::new([
+            [1, 2, 3],
+            [4, 5, 6],
+        ]);
+        for number in [1, 5, 3] {
+            board.draw_number(number);
+        }
+        assert_eq!(board.winning_pattern(&[WinPattern::Diagonals, WinPattern::X]), None);
+        assert_eq!(board.winning_pattern(&[WinPattern::FourCorners]), None);
+        board.draw_number(6);
+        assert_eq!(board.winning_pattern(&[WinPattern::FourCorners]), None);
+        board.draw_number(4);
+        assert_eq!(board.winning_pattern(&[WinPattern::Diagonals, WinPattern::FourCorners]), Some(&WinPattern::FourCorners));
+    }
+
+    #[test]
+    fn win_with_patterns() -> anyhow::Result<()> {
+        let mut game : Game = load_sample_sections(4)?;
+        game.with_patterns(vec![WinPattern::Blackout])?;
+
+        // the second board is the only one without the last number drawn
+        match game.timeline().first_winner() {
+            SimulationResult::Winner(WinningBoard {
+                board,
+                last_number,
+                pattern,
+            }) => {
+                assert_eq!(last_number, 3);
+                assert_eq!(board.sum_of_all_unmarked_numbers(), 0);
+                assert_eq!(pattern, WinPattern::Blackout);
+            },
+            _ => return Err(anyhow::anyhow!("invalid simulation result")),
+        }
+
+        let mut game : Game = load_sample_sections(4)?;
+        game.with_patterns(vec!["x".parse()?, "columns".parse()?])?;
+        assert!(matches!(game.timeline().first_winner(), SimulationResult::Winner(WinningBoard { pattern: WinPattern::Columns, .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_patterns() -> anyhow::Result<()> {
+        let mut game : Game = load_sample_sections(4)?;
+        let small = WinPattern::Custom("#.\n.#".parse()?);
+        assert_eq!(game.with_patterns(vec![small]), Err(BoardError::MaskSize { rows: 2, columns: 2, expected_rows: 5, expected_columns: 5 }));
+
+        assert!("#.\n#".parse::<Mask>().is_err());
+        assert!("#x".parse::<Mask>().is_err());
+        assert!("".parse::<Mask>().is_err());
+        assert!("stripes".parse::<WinPattern>().is_err());
+        assert_eq!(plus()?.to_string(), "custom 5x5 mask");
+        Ok(())
+    }
+
+    #[test]
+    fn timeline() -> anyhow::Result<()> {
+        let timeline = load_sample_sections::<Game>(4)?.timeline();
+
+        assert_eq!(timeline.wins, vec![
+            Win { draw: 11, number: 24, board: 2, score: 4512, pattern: WinPattern::Rows },
+            Win { draw: 13, number: 16, board: 0, score: 2192, pattern: WinPattern::Rows },
+            Win { draw: 14, number: 13, board: 1, score: 1924, pattern: WinPattern::Columns },
+        ]);
+        assert!(timeline.never_won.is_empty());
+        assert_eq!(timeline.rank(2), timeline.wins.get(1));
+        assert_eq!(timeline.rank(0), None);
+        assert_eq!(timeline.rank(4), None);
+        assert_eq!(timeline.wins.first().map(|win| win.to_string()), Some("board 2 won with rows on draw 11 (24), scoring 4512".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn boards_that_never_win() -> anyhow::Result<()> {
+        let game : Game = sections("
+            1,2,3
+
+            1 2
+            5 6
+
+            7 8
+            9 3
+
+            1 3
+            5 6
+
+            4 3
+            8 1
+            ").try_into()?;
+        let timeline = game.timeline();
+
+        // boards winning on the same draw keep their board order
+        assert_eq!(timeline.wins.iter().map(|win| (win.draw, win.board)).collect::<Vec<_>>(), vec![(1, 0), (2, 2), (2, 3)]);
+        assert_eq!(timeline.never_won, vec![1]);
+        assert!(matches!(timeline.last_winner(), SimulationResult::Winner(WinningBoard { last_number: 3, .. })));
+
+        let mut game : Game = load_sample_sections(4)?;
+        game.with_patterns(vec![WinPattern::Blackout])?;
+        let timeline = game.timeline();
+        // only the first board has 1, the last number drawn, and 26 comes just before it
+        assert_eq!(timeline.wins.iter().map(|win| (win.number, win.board)).collect::<Vec<_>>(), vec![(3, 1), (26, 2), (1, 0)]);
+        assert!(timeline.never_won.is_empty());
+        Ok(())
+    }
+
+    /// A game of `boards` random boards drawing every number in `0..numbers`
+    /// in a random order.
+    fn generated_game(seed : u64, boards : usize, rows : usize, columns : usize, numbers : i64) -> Game {
+        let mut rng = Rng::new(seed);
+        let mut to_draw : Vec<i64> = (0..numbers).collect();
+        rng.shuffle(&mut to_draw);
+        Game {
+            to_draw,
+            boards: (0..boards)
+                .map(|_| Board::new((0..rows).map(|_| (0..columns).map(|_| rng.below(numbers as usize) as i64).collect::<Vec<i64>>())))
+                .collect(),
+            patterns: WinPattern::defaults(),
+        }
+    }
+
+    #[rstest]
+    #[case(vec![WinPattern::Rows, WinPattern::Columns])]
+    #[case(vec![WinPattern::Diagonals])]
+    #[case(vec![WinPattern::X, WinPattern::FourCorners])]
+    #[case(vec![WinPattern::Blackout])]
+    #[case(vec![plus().unwrap(), WinPattern::Columns])]
+    fn indexed_timeline(#[case] patterns : Vec<WinPattern>) -> anyhow::Result<()> {
+        let mut game : Game = load_sample_sections(4)?;
+        game.with_patterns(patterns.clone())?;
+        assert_eq!(game.indexed_timeline(), game.timeline());
+
+        for seed in 1..20 {
+            let mut game = generated_game(seed, 50, 5, 5, 60);
+            game.with_patterns(patterns.clone())?;
+            assert_eq!(game.indexed_timeline(), game.timeline());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn indexed_timeline_odd_boards() {
+        for (rows, columns) in [(1, 1), (1, 4), (3, 1), (2, 7)] {
+            let mut game = generated_game(7, 30, rows, columns, 20);
+            game.patterns = vec![WinPattern::FourCorners, WinPattern::Diagonals, WinPattern::Rows];
+            assert_eq!(game.indexed_timeline(), game.timeline());
+        }
+    }
+
+    #[test]
+    fn indexed_timeline_many_boards() {
+        let game = generated_game(42, 500, 5, 5, 1_000);
+        assert_eq!(game.indexed_timeline(), game.timeline());
+    }
+
+    /// Run with `cargo test --release --features bench --bin day4 bench -- --nocapture`.
+    #[cfg(feature = "bench")]
+    #[test]
+    fn bench_indexed_timeline() {
+        let game = generated_game(42, 5_000, 5, 5, 1_000);
+
+        let start = std::time::Instant::now();
+        let scanned = game.timeline();
+        let scan = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let indexed = game.indexed_timeline();
+        let index = start.elapsed();
+
+        println!("{} boards, {} draws: scan {:?}, indexed {:?}", game.boards.len(), game.to_draw.len(), scan, index);
+        assert_eq!(scanned, indexed);
+    }
+
+    #[test]
+    fn earliest_winner() -> anyhow::Result<()> {
+        let game : Game = load_sample_sections(4)?;
+        let solver = BoardSolver::new(&game.to_draw, 5, 5);
+        let board = solver.earliest_winner()?;
+
+        let timeline = solver.play(&board);
+        assert_eq!(timeline.wins[0].draw, 4);
+        // no board of the sample wins sooner
+        assert!(game.timeline().wins.iter().all(|win| win.draw >= 4));
+
+        let wide = BoardSolver::new(&game.to_draw, 2, 6).earliest_winner()?;
+        assert_eq!(solver.play(&wide).wins[0].draw, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn never_winner() -> anyhow::Result<()> {
+        let game : Game = load_sample_sections(4)?;
+        let solver = BoardSolver::new(&game.to_draw, 5, 5);
+        assert_eq!(solver.never_winner(), Err(SolveError::NotEnoughUndrawn { needed: 5, available: 0 }));
+
+        let solver = solver.with_pool((0..40).collect());
+        let board = solver.never_winner()?;
+        assert!(solver.play(&board).wins.is_empty());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(5, 5, 11, 24 * 300)]
+    #[case(5, 5, 8, 0)]
+    #[case(5, 5, 4, 11 * 300)]
+    #[case(3, 4, 23, 19 * 100)]
+    #[case(1, 4, 2, 9 * 60)]
+    #[case(4, 1, 6, 23 * 5)]
+    #[case(1, 1, 3, 0)]
+    fn winner_at(#[case] rows : usize, #[case] columns : usize, #[case] draw : usize, #[case] score : i64) -> anyhow::Result<()> {
+        let game : Game = load_sample_sections(4)?;
+        let solver = BoardSolver::new(&game.to_draw, rows, columns).with_pool((0..40).collect());
+        let board = solver.winner_at(draw, score)?;
+
+        assert_eq!(board.size(), (rows, columns));
+        let mut numbers : Vec<i64> = board.data.iter().map(|cell| cell.number).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        assert_eq!(numbers.len(), rows * columns);
+
+        let win = &solver.play(&board).wins[0];
+        assert_eq!((win.draw, win.score), (draw, score));
+        Ok(())
+    }
+
+    #[test]
+    fn impossible_winners() -> anyhow::Result<()> {
+        let game : Game = load_sample_sections(4)?;
+        let solver = BoardSolver::new(&game.to_draw, 5, 5);
+
+        assert_eq!(solver.winner_at(2, 0).unwrap_err(), SolveError::TooEarly { draw: 2, needed: 4 });
+        assert_eq!(solver.winner_at(27, 0).unwrap_err(), SolveError::DrawOutOfRange { draw: 27, draws: 27 });
+        // every score is a multiple of the number drawn
+        assert_eq!(solver.winner_at(10, 7).unwrap_err(), SolveError::Score { draw: 10, score: 7 });
+        // draw 10 is 21, and the 16 numbers drawn after it only add up to 238
+        assert_eq!(solver.winner_at(10, 21 * 100_000_000).unwrap_err(), SolveError::ScoreTooHigh { draw: 10, score: 21 * 100_000_000, max: 21 * 238 });
+        // the other rows need numbers drawn later, but the pool has none left
+        // after the last draw
+        assert_eq!(solver.winner_at(26, 1000).unwrap_err(), SolveError::Score { draw: 26, score: 1000 });
+        assert_eq!(BoardSolver::new(&[1, 2, 3], 2, 2).winner_at(2, 0).unwrap_err(), SolveError::PoolTooSmall { needed: 4, available: 3 });
+        assert_eq!(BoardSolver::new(&[1, 2, 1, 3], 1, 1).winner_at(2, 0).unwrap_err(), SolveError::RepeatedNumber { draw: 2, number: 1 });
+        Ok(())
+    }
+
+    #[test]
+    fn subset_sums() -> anyhow::Result<()> {
+        assert_eq!(subset_with_sum(&[1, 2, 3, 4], 2..=2, 7)?.map(|mut subset| { subset.sort(); subset }), Some(vec![3, 4]));
+        assert_eq!(subset_with_sum(&[1, 2, 3, 4], 3..=3, 5)?, None);
+        assert_eq!(subset_with_sum(&[1, 2, 3, 4], 1..=4, 10)?.map(|subset| subset.len()), Some(4));
+        assert_eq!(subset_with_sum(&[5], 0..=1, 0)?, Some(vec![]));
+        assert_eq!(subset_with_sum(&[1, -2, 3], 1..=2, 1), Err(SolveError::NegativeNumbers));
+        Ok(())
+    }
+
+    #[test]
+    fn subset_sums_of_large_numbers() -> anyhow::Result<()> {
+        let numbers = [1_000_000_000, 2_000_000_000, 3_000_000_000];
+        assert_eq!(subset_with_sum(&numbers, 1..=2, 5_000_000_000), Err(SolveError::TableTooLarge { sum: 5_000_000_000, max: SUBSET_TABLE_CELLS }));
+        // out of reach, so no table is needed
+        assert_eq!(subset_with_sum(&numbers, 1..=2, 6_000_000_000)?, None);
+
+        let to_draw = [7, 1_000_000_000, 1_000_000_001, 1_000_000_002, 1_000_000_003];
+        let solver = BoardSolver::new(&to_draw, 2, 2);
+        assert_eq!(solver.winner_at(1, 1_000_000_000 * 2_000_000_003).unwrap_err(), SolveError::TableTooLarge { sum: 2_000_000_003, max: SUBSET_TABLE_CELLS });
+        Ok(())
+    }
+
+
+    fn close(a : f64, b : f64, tolerance : f64) -> bool {
+        (a - b).abs() < tolerance
+    }
+
+    #[test]
+    fn exact_odds() -> anyhow::Result<()> {
+        let patterns = WinPattern::defaults();
+        let single = |number| Board::new([[number]]);
+        let results = odds::exact(&[single(1), single(2)], &patterns, &[1, 2, 3])?;
+        assert!(close(results[0].first, 0.5, 1e-12));
+        assert!(close(results[0].expected_draws.unwrap_or_default(), 2.0, 1e-12));
+
+        // drawing 1 first wins on both boards at once, drawing 2 first only on the first
+        let results = odds::exact(&[Board::new([[1, 2]]), single(1)], &patterns, &[1, 2])?;
+        assert!(close(results[0].first, 0.75, 1e-12));
+        assert!(close(results[1].first, 0.25, 1e-12));
+        assert!(close(results[0].expected_draws.unwrap_or_default(), 1.0, 1e-12));
+        assert!(close(results[1].expected_draws.unwrap_or_default(), 1.5, 1e-12));
+
+        let results = odds::exact(&[Board::new([[1, 9]])], &[WinPattern::Rows], &[1, 2])?;
+        assert_eq!(results, vec![odds::Odds { first: 0.0, expected_draws: None }]);
+
+        assert_eq!(odds::exact(&[single(1)], &patterns, &(0..17).collect::<Vec<i64>>()), Err(odds::OddsError::PoolTooLarge { size: 17, max: 16 }));
+        Ok(())
+    }
+
+    #[test]
+    fn monte_carlo_odds() -> anyhow::Result<()> {
+        let boards = vec![
+            Board::new([[1, 2], [3, 4]]),
+            Board::new([[4, 5], [6, 7]]),
+            Board::new([[1, 8], [7, 2]]),
+        ];
+        let pool : Vec<i64> = (1..=9).collect();
+        let patterns = WinPattern::defaults();
+        let exact = odds::exact(&boards, &patterns, &pool)?;
+        let estimated = odds::monte_carlo(&boards, &patterns, &pool, 20_000, &mut Rng::new(7));
+
+        assert!(close(exact.iter().map(|odds| odds.first).sum(), 1.0, 1e-9));
+        for (exact, estimated) in exact.iter().zip(estimated.iter()) {
+            assert!(close(exact.first, estimated.first, 0.02), "{} vs {}", exact, estimated);
+            assert!(close(exact.expected_draws.unwrap_or_default(), estimated.expected_draws.unwrap_or_default(), 0.05), "{} vs {}", exact, estimated);
+        }
+        assert_eq!(estimated, odds::monte_carlo(&boards, &patterns, &pool, 20_000, &mut Rng::new(7)));
+        Ok(())
+    }
+
+    fn tournament(elimination : Elimination) -> anyhow::Result<Tournament> {
+        let mut tournament : Tournament = sections("
+            rounds 3
+
+            1,2,5,6
+
+            8,4,9,2
+
+            3,7
+
+            1 2
+            3 4
+
+            5 6
+            7 8
+
+            1 5
+            3 7
+            ").try_into()?;
+        tournament.elimination = elimination;
+        Ok(tournament)
+    }
+
+    fn standing(board : usize, total : i64, rounds_won : usize, eliminated : Option<usize>) -> Standing {
+        Standing {
+            board,
+            total,
+            rounds_won,
+            eliminated,
+        }
+    }
+
+    #[test]
+    fn tournament_scoring() -> anyhow::Result<()> {
+        let tournament = tournament(Elimination::Keep)?;
+        assert_eq!(tournament.rounds.len(), 3);
+        assert_eq!(tournament.boards.len(), 3);
+
+        let (rounds, standings) = tournament.play();
+        assert_eq!(rounds[0], Round { scores: vec![(0, 14), (1, 90), (2, 50)], winner: Some(0), eliminated: vec![] });
+        // boards start clean again, so the 1 and 2 drawn before don't count
+        assert_eq!(rounds[1], Round { scores: vec![(0, 8), (1, 0), (2, 0)], winner: Some(0), eliminated: vec![] });
+        assert_eq!(rounds[2], Round { scores: vec![(0, 0), (1, 0), (2, 42)], winner: Some(2), eliminated: vec![] });
+        assert_eq!(standings, vec![
+            standing(2, 92, 1, None),
+            standing(1, 90, 0, None),
+            standing(0, 22, 2, None),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn tournament_eliminating_non_winners() -> anyhow::Result<()> {
+        let (rounds, standings) = tournament(Elimination::NonWinners)?.play();
+        assert_eq!(rounds[1].eliminated, vec![1, 2]);
+        // the last board never wins the final round but is never eliminated
+        assert_eq!(rounds[2], Round { scores: vec![(0, 0)], winner: None, eliminated: vec![] });
+        assert_eq!(standings, vec![
+            standing(0, 22, 2, None),
+            standing(1, 90, 0, Some(2)),
+            standing(2, 50, 0, Some(2)),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn tournament_eliminating_lowest() -> anyhow::Result<()> {
+        let (rounds, standings) = tournament(Elimination::Lowest(1))?.play();
+        assert_eq!(rounds.iter().map(|round| round.eliminated.clone()).collect::<Vec<_>>(), vec![vec![0], vec![2], vec![]]);
+        assert_eq!(rounds[1], Round { scores: vec![(1, 0), (2, 0)], winner: None, eliminated: vec![2] });
+        assert_eq!(standings, vec![
+            standing(1, 90, 0, None),
+            standing(2, 50, 0, Some(2)),
+            standing(0, 14, 1, Some(1)),
+        ]);
+        assert_eq!(standings[2].to_string(), "board 0 scored 14 winning 1 rounds, eliminated in round 1");
+
+        // ties drop the later board
+        let mut tied : Tournament = sections("rounds 1\n\n5,6\n\n1 2\n\n2 1\n\n3 4").try_into()?;
+        tied.elimination = Elimination::Lowest(1);
+        let (rounds, _) = tied.play();
+        assert_eq!(rounds[0].scores, vec![(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(rounds[0].eliminated, vec![2]);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("keep", Some(Elimination::Keep))]
+    #[case("nonwinners", Some(Elimination::NonWinners))]
+    #[case("lowest:2", Some(Elimination::Lowest(2)))]
+    #[case("lowest", None)]
+    #[case("lowest:x", None)]
+    #[case("highest:1", None)]
+    fn elimination(#[case] input : &str, #[case] expected : Option<Elimination>) {
+        assert_eq!(input.parse::<Elimination>().ok(), expected);
+    }
+
+    #[test]
+    fn single_number_rounds() -> anyhow::Result<()> {
+        let tournament : Tournament = sections("rounds 2\n\n7\n\n4,3\n\n7\n\n3").try_into()?;
+        assert_eq!(tournament.rounds, vec![vec![7], vec![4, 3]]);
+        assert_eq!(tournament.boards.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn tournament_masks() -> anyhow::Result<()> {
+        let mut tournament = tournament(Elimination::Keep)?;
+        let small = WinPattern::Custom("#".parse()?);
+        assert_eq!(tournament.with_patterns(vec![small]), Err(BoardError::MaskSize { rows: 1, columns: 1, expected_rows: 2, expected_columns: 2 }));
+        tournament.with_patterns(vec![WinPattern::Custom("##\n..".parse()?)])?;
+        assert_eq!(tournament.play().0[0].winner, Some(0));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("1 2\n3 4", "General Error: missing round count, expected rounds N")]
+    #[case("1,2\n\n1 2\n3 4", "General Error: missing round count, expected rounds N")]
+    #[case("rounds 0\n\n1 2\n3 4", "General Error: missing numbers to draw")]
+    #[case("rounds x\n\n1,2\n\n1 2\n3 4", "Could not parse int")]
+    #[case("rounds 2\n\n1,2\n\n1 2\n3 4", "General Error: missing numbers to draw in round 2")]
+    #[case("rounds 1\n\n1,2\n\n1 2\n3 4\n\n3,4", "Could not parse int")]
+    #[case("rounds 1\n\n1,2\n\n1 2\n3 4\n\n1 2 3\n4 5 6", "General Error: board 2 is 2x3, expected 2x2 like board 1")]
+    fn malformed_tournaments(#[case] input : &str, #[case] message : &str) {
+        let tournament : Result<Tournament, InputFileError> = sections(input).try_into();
+        match tournament {
+            Err(error) => assert_eq!(error.to_string(), message),
+            Ok(_) => panic!("expected {}", message),
+        }
+    }
 }
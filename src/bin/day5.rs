@@ -1,16 +1,61 @@
 use aoc2021::input::{InputFileError, InputFile, load_input};
 use std::str::FromStr;
-use std::cmp::{min, max};
 use aoc2021::Point;
 
 fn main() -> anyhow::Result<()> {
     let field : VentField = load_input(5)?;
 
     println!("part1: {}", field.part1());
+    println!("part2: {}", field.part2());
+
+    for arg in std::env::args().skip(1) {
+        if let Some(mode) = arg.strip_prefix("--mode=") {
+            let mode : LineMode = mode.parse()?;
+            println!("{:?}: {}", mode, field.number_of_points_where_at_least_two_lines_overlap(mode));
+        } else if let Some(mode) = arg.strip_prefix("--geometric=") {
+            let mode : LineMode = mode.parse()?;
+            println!("{:?}: {}", mode, field.overlapping_points(mode).len());
+        }
+    }
 
     Ok(())
 }
 
+/// Which segments get drawn. Each mode includes the lines of the one before.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+enum LineMode {
+    /// Horizontal and vertical lines only.
+    #[default]
+    Axis,
+    /// Axis lines and 45° diagonals.
+    Diagonal,
+    /// Lines of any slope.
+    All,
+}
+
+impl LineMode {
+    fn includes(&self, segment : &LineSegment) -> bool {
+        match self {
+            LineMode::Axis => segment.is_horizontal() || segment.is_vertical(),
+            LineMode::Diagonal => segment.is_horizontal() || segment.is_vertical() || segment.is_diagonal(),
+            LineMode::All => true,
+        }
+    }
+}
+
+impl FromStr for LineMode {
+    type Err = InputFileError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "axis" => Ok(LineMode::Axis),
+            "diagonal" => Ok(LineMode::Diagonal),
+            "all" => Ok(LineMode::All),
+            _ => Err(InputFileError::GeneralError(format!("unknown line mode {}", s))),
+        }
+    }
+}
+
 impl FromStr for LineSegment {
     type Err = InputFileError;
 
@@ -44,7 +89,7 @@ impl TryFrom<InputFile> for VentField {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 struct LineSegment {
     from : Point,
     to : Point,
@@ -58,74 +103,132 @@ impl LineSegment {
         self.from.x == self.to.x
     }
 
-    fn line(&self, include_diagnal : bool) -> Vec<Point> {
-        let mut line = vec![];
-        if self.is_horizontal() {
-            for x in self.min().x..=self.max().x {
-                line.push(Point {
-                    x,
-                    y: self.from.y
-                });
-            }
-        } else if self.is_vertical() {
-            for y in self.min().y..=self.max().y {
-                line.push(Point {
-                    y,
-                    x: self.from.x,
-                });
-            }
-        } else if include_diagnal {
-            let m = ((self.from.y as i64) - (self.to.y as i64)) / ((self.from.x as i64) - (self.to.x as i64));
-            let b = (self.from.y as i64) - m * (self.from.x as i64);
-
-            for x in min(self.from.x, self.to.x)..=max(self.from.x, self.to.x) {
-                line.push(Point {
-                    x,
-                    y: ((m * (x as i64) + b) as usize),
-                })
-            }
-        }
+    fn is_diagonal(&self) -> bool {
+        self.from.x.abs_diff(self.to.x) == self.from.y.abs_diff(self.to.y)
+    }
+
+    /// Every point with integer coordinates on the segment, from `from` to
+    /// `to`. Stepping by the gcd of both deltas lands exactly on each of them
+    /// whatever the slope.
+    fn points(&self) -> impl Iterator<Item = Point> {
+        let dx = self.to.x as i64 - self.from.x as i64;
+        let dy = self.to.y as i64 - self.from.y as i64;
+        let steps = gcd(dx.abs(), dy.abs());
+        let (step_x, step_y) = if steps == 0 {
+            (0, 0)
+        } else {
+            (dx / steps, dy / steps)
+        };
+        let from = self.from;
+        (0..=steps).map(move |i| Point {
+            x: (from.x as i64 + i * step_x) as usize,
+            y: (from.y as i64 + i * step_y) as usize,
+        })
+    }
 
-        line
+    fn delta(&self) -> (i64, i64) {
+        (self.to.x as i64 - self.from.x as i64, self.to.y as i64 - self.from.y as i64)
     }
 
-    fn min(&self) -> Point {
-        if self.is_horizontal() {
-            if self.from.x < self.to.x {
-                self.from
-            } else {
-                self.to
+    /// The lattice points this segment shares with `other`, worked out from
+    /// the endpoints alone.
+    fn overlap(&self, other : &LineSegment) -> Option<Overlap> {
+        // measure along a segment that isn't a single point if there is one
+        let (a, b) = if self.from == self.to {
+            (other, self)
+        } else {
+            (self, other)
+        };
+        // cross products of two deltas need twice their bits
+        let wide = |(x, y) : (i64, i64)| (x as i128, y as i128);
+        let (px, py) = (a.from.x as i128, a.from.y as i128);
+        let (dx, dy) = wide(a.delta());
+        let (ex, ey) = wide(b.delta());
+        let (qx, qy) = (b.from.x as i128 - px, b.from.y as i128 - py);
+        let cross = |(x1, y1) : (i128, i128), (x2, y2) : (i128, i128)| x1 * y2 - y1 * x2;
+
+        let denominator = cross((dx, dy), (ex, ey));
+        if denominator != 0 {
+            // the lines cross once, at a + t * (dx, dy) and b + u * (ex, ey)
+            // for t and u in 0..=1
+            let sign = denominator.signum();
+            let (t, u, denominator) = (cross((qx, qy), (ex, ey)) * sign, cross((qx, qy), (dx, dy)) * sign, denominator.abs());
+            if !(0..=denominator).contains(&t) || !(0..=denominator).contains(&u) {
+                return None;
             }
-        } else if self.is_vertical() {
-            if self.from.y < self.to.y {
-                self.from
-            } else {
-                self.to
+            // t / denominator in lowest terms only lands on a lattice point if
+            // its denominator divides both deltas, which also keeps the
+            // products below the size of a delta
+            let common = gcd(t, denominator).max(1);
+            let (t, denominator) = (t / common, denominator / common);
+            if dx % denominator != 0 || dy % denominator != 0 {
+                return None;
             }
+            return Some(Overlap::Crossing(Point {
+                x: (px + t * (dx / denominator)) as usize,
+                y: (py + t * (dy / denominator)) as usize,
+            }));
+        }
+
+        if cross((qx, qy), (dx, dy)) != 0 {
+            return None;
+        }
+        if a.from == a.to {
+            return (a.from == b.from).then_some(Overlap::Crossing(a.from));
+        }
+
+        // collinear, so both segments are made of steps of the same lattice
+        // vector and overlap over a range of those steps
+        let steps = gcd(dx.abs(), dy.abs());
+        let (sx, sy) = (dx / steps, dy / steps);
+        let along = |point : Point| if sx != 0 {
+            (point.x as i128 - px) / sx
         } else {
-            self.from
+            (point.y as i128 - py) / sy
+        };
+        let (start, end) = (along(b.from), along(b.to));
+        let (low, high) = (start.min(end).max(0), start.max(end).min(steps));
+        let at = |i : i128| Point {
+            x: (px + i * sx) as usize,
+            y: (py + i * sy) as usize,
+        };
+        match low.cmp(&high) {
+            std::cmp::Ordering::Greater => None,
+            std::cmp::Ordering::Equal => Some(Overlap::Crossing(at(low))),
+            std::cmp::Ordering::Less => Some(Overlap::Collinear(LineSegment {
+                from: at(low),
+                to: at(high),
+            })),
         }
     }
 
-    fn max(&self) -> Point {
-        if self.is_horizontal() {
-            if self.from.x > self.to.x {
-                self.from
-            } else {
-                self.to
-            }
-        } else if self.is_vertical() {
-            if self.from.y > self.to.y {
-                self.from
-            } else {
-                self.to
-            }
+    /// The points of the segment, or none if `mode` leaves it out.
+    fn line(&self, mode : LineMode) -> Vec<Point> {
+        if mode.includes(self) {
+            self.points().collect()
         } else {
-            self.from
+            vec![]
         }
     }
 }
 
+/// Lattice points shared by two segments.
+#[derive(Debug, PartialEq, Clone)]
+enum Overlap {
+    /// The segments meet at a single point.
+    Crossing(Point),
+    /// The segments lie on the same line and share every point of this one.
+    Collinear(LineSegment),
+}
+
+fn gcd<T : Copy + Default + PartialEq + std::ops::Rem<Output = T>>(a : T, b : T) -> T {
+    if b == T::default() {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct VentField {
     lines : Vec<LineSegment>
@@ -138,24 +241,27 @@ struct SparseMatrix {
 
 
 impl SparseMatrix {
+    #[cfg(test)]
     fn new(rows: Vec<Vec<i64>>) -> Self {
         let mut matrix = SparseMatrix::default();
-        for row in 0..rows.len() {
-            for col in 0..rows[row].len() {
-                if rows[row][col] > 0 {
-                    matrix.set(col, row, rows[row][col]);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                if *value > 0 {
+                    matrix.set(x, y, *value);
                 }
             }
         }
         matrix
     }
 
+    #[cfg(test)]
     fn set(&mut self, x : usize, y : usize, val : i64) {
         *self.points.entry(Point{
             x,
             y,
         }).or_insert(0) += val
     }
+    #[cfg(test)]
     fn get(&mut self, x : usize, y : usize) -> i64 {
         match self.points.get(&Point{
             x,
@@ -166,6 +272,21 @@ impl SparseMatrix {
         }
     }
 
+    fn values(&self) -> std::collections::hash_map::Values<'_, Point, i64> {
+        self.points.values()
+    }
+
+}
+
+/// Counts how many lines cover each point.
+trait OverlapStorage {
+    fn increment(&mut self, x : usize, y : usize);
+
+    /// The number of points covered by at least two lines.
+    fn overlapping(&self) -> i64;
+}
+
+impl OverlapStorage for SparseMatrix {
     fn increment(&mut self, x : usize, y : usize) {
         *self.points.entry(Point {
             x,
@@ -173,16 +294,88 @@ impl SparseMatrix {
         }).or_insert(0) += 1
     }
 
-    fn values(&self) -> std::collections::hash_map::Values<Point, i64> {
-        self.points.values()
+    fn overlapping(&self) -> i64 {
+        self.values().filter(|val| **val >= 2).count() as i64
+    }
+}
+
+/// The `width` by `height` points starting at `min`.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+struct Bounds {
+    min : Point,
+    width : usize,
+    height : usize,
+}
+
+impl Bounds {
+    fn cells(&self) -> Option<usize> {
+        self.width.checked_mul(self.height)
+    }
+
+    /// Where `x, y` is in a row by row grid of these bounds, `None` outside
+    /// of them.
+    fn index(&self, x : usize, y : usize) -> Option<usize> {
+        let (dx, dy) = (x.checked_sub(self.min.x)?, y.checked_sub(self.min.y)?);
+        if dx < self.width && dy < self.height {
+            Some(dy * self.width + dx)
+        } else {
+            None
+        }
+    }
+}
+
+/// A count for every point within the bounds of the field. Counts saturate,
+/// which can't change whether a point overlaps.
+#[derive(Debug, PartialEq, Clone)]
+struct DenseGrid {
+    bounds : Bounds,
+    cells : Vec<u16>,
+}
+
+impl DenseGrid {
+    fn new(bounds : Bounds) -> Self {
+        DenseGrid {
+            bounds,
+            cells: vec![0; bounds.width * bounds.height],
+        }
     }
+}
+
+impl OverlapStorage for DenseGrid {
+    fn increment(&mut self, x : usize, y : usize) {
+        let index = match self.bounds.index(x, y) {
+            Some(index) => index,
+            None => panic!("{},{} is outside the grid {:?}", x, y, self.bounds),
+        };
+        let cell = &mut self.cells[index];
+        *cell = cell.saturating_add(1);
+    }
+
+    fn overlapping(&self) -> i64 {
+        self.cells.iter().filter(|cell| **cell >= 2).count() as i64
+    }
+}
 
+/// Where `VentField` counts overlaps.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Backend {
+    Sparse,
+    Dense(Bounds),
+}
+
+impl Backend {
+    /// The most cells a dense grid may have, 256 MiB of counts.
+    const MAX_DENSE_CELLS : usize = 1 << 27;
+
+    /// A map costs tens of bytes per point against two for a grid cell, so
+    /// the grid wins once lines cover more than about one cell in sixteen.
+    const DENSE_CELLS_PER_POINT : usize = 16;
 }
 
 #[derive(Debug, PartialEq, Default)]
-struct Intersections {
-    matrix: SparseMatrix,
-    include_diagnal : bool,
+struct Intersections<S = SparseMatrix> {
+    matrix: S,
+    mode : LineMode,
 }
 
 impl Intersections {
@@ -190,59 +383,144 @@ impl Intersections {
     fn new(rows: Vec<Vec<i64>>) -> Self {
         Intersections {
             matrix: SparseMatrix::new(rows),
-            include_diagnal: false,
+            mode: LineMode::Axis,
         }
     }
     #[cfg(test)]
     fn new_with_diagnal(rows: Vec<Vec<i64>>) -> Self {
         Intersections {
             matrix: SparseMatrix::new(rows),
-            include_diagnal: true,
+            mode: LineMode::Diagonal,
         }
     }
 
+}
+
+impl<S : OverlapStorage> Intersections<S> {
     fn add(&mut self, segment : &LineSegment) {
-        for point in segment.line(self.include_diagnal).iter() {
+        for point in segment.line(self.mode).iter() {
             self.matrix.increment(point.x, point.y)
         }
     }
-
 }
 
 impl VentField {
     fn part1(&self) -> i64 {
-        self.number_of_points_where_at_least_two_lines_overlap(false)
+        self.number_of_points_where_at_least_two_lines_overlap(LineMode::Axis)
     }
 
     fn part2(&self) -> i64 {
-        self.number_of_points_where_at_least_two_lines_overlap(true)
+        self.number_of_points_where_at_least_two_lines_overlap(LineMode::Diagonal)
     }
 
-    fn number_of_points_where_at_least_two_lines_overlap(&self, include_diagnal : bool) -> i64 {
-        let intersections = self.intersections(include_diagnal);
-        let mut count = 0;
+    fn number_of_points_where_at_least_two_lines_overlap(&self, mode : LineMode) -> i64 {
+        self.count_overlaps(mode, self.backend(mode))
+    }
 
-        for val in intersections.matrix.values() {
-            if val >= &2 {
-                count += 1;
-            }
+    fn count_overlaps(&self, mode : LineMode, backend : Backend) -> i64 {
+        match backend {
+            Backend::Sparse => self.intersections_in(mode, SparseMatrix::default()).matrix.overlapping(),
+            Backend::Dense(bounds) => self.intersections_in(mode, DenseGrid::new(bounds)).matrix.overlapping(),
+        }
+    }
+
+    /// The smallest bounds holding every segment `mode` includes.
+    fn bounds(&self, mode : LineMode) -> Bounds {
+        let mut points = self.lines.iter()
+            .filter(|segment| mode.includes(segment))
+            .flat_map(|segment| [segment.from, segment.to]);
+        let first = match points.next() {
+            Some(point) => point,
+            None => return Bounds::default(),
+        };
+        let (low, high) = points.fold((first, first), |(low, high), point| (
+            Point { x: low.x.min(point.x), y: low.y.min(point.y) },
+            Point { x: high.x.max(point.x), y: high.y.max(point.y) },
+        ));
+        Bounds {
+            min: low,
+            width: high.x - low.x + 1,
+            height: high.y - low.y + 1,
         }
+    }
+
+    /// Picks a dense grid covering every segment `mode` includes when it
+    /// isn't too big and the lines fill enough of it, otherwise a sparse map.
+    fn backend(&self, mode : LineMode) -> Backend {
+        let bounds = self.bounds(mode);
+        let points : usize = self.lines.iter()
+            .filter(|segment| mode.includes(segment))
+            .map(|segment| {
+                let (dx, dy) = segment.delta();
+                gcd(dx.abs(), dy.abs()) as usize + 1
+            })
+            .sum();
 
-        count
+        match bounds.cells() {
+            Some(cells) if cells <= Backend::MAX_DENSE_CELLS && cells <= points.saturating_mul(Backend::DENSE_CELLS_PER_POINT) => Backend::Dense(bounds),
+            _ => Backend::Sparse,
+        }
     }
 
+    #[cfg(test)]
     fn part2_intersections(&self) -> Intersections {
-        self.intersections(true)
+        self.intersections(LineMode::Diagonal)
     }
 
+    #[cfg(test)]
     fn part1_intersections(&self) -> Intersections {
-        self.intersections(false)
+        self.intersections(LineMode::Axis)
+    }
+
+    /// Every overlap between two segments that `mode` includes. Segments are
+    /// swept left to right, so only those whose x ranges meet are compared.
+    fn overlaps(&self, mode : LineMode) -> Vec<Overlap> {
+        let x_range = |segment : &LineSegment| (segment.from.x.min(segment.to.x), segment.from.x.max(segment.to.x));
+        let y_range = |segment : &LineSegment| (segment.from.y.min(segment.to.y), segment.from.y.max(segment.to.y));
+        let mut segments : Vec<&LineSegment> = self.lines.iter().filter(|segment| mode.includes(segment)).collect();
+        segments.sort_by_key(|segment| x_range(segment));
+
+        let mut overlaps = vec![];
+        for (i, a) in segments.iter().enumerate() {
+            let (_, right) = x_range(a);
+            let (top, bottom) = y_range(a);
+            for b in segments[i + 1..].iter().take_while(|b| x_range(b).0 <= right) {
+                let (b_top, b_bottom) = y_range(b);
+                if b_top > bottom || b_bottom < top {
+                    continue;
+                }
+                if let Some(overlap) = a.overlap(b) {
+                    overlaps.push(overlap);
+                }
+            }
+        }
+        overlaps
     }
 
-    fn intersections(&self, include_diagnal: bool) -> Intersections {
+    /// The points where at least two lines overlap, without rasterizing
+    /// every segment.
+    fn overlapping_points(&self, mode : LineMode) -> std::collections::HashSet<Point> {
+        let mut points = std::collections::HashSet::new();
+        for overlap in self.overlaps(mode) {
+            match overlap {
+                Overlap::Crossing(point) => {
+                    points.insert(point);
+                },
+                Overlap::Collinear(segment) => points.extend(segment.points()),
+            }
+        }
+        points
+    }
+
+    #[cfg(test)]
+    fn intersections(&self, mode : LineMode) -> Intersections {
+        self.intersections_in(mode, SparseMatrix::default())
+    }
+
+    fn intersections_in<S : OverlapStorage>(&self, mode : LineMode, matrix : S) -> Intersections<S> {
         let mut intersections = Intersections {
-            include_diagnal,
-            ..Intersections::default()
+            matrix,
+            mode,
         };
         for segment in self.lines.iter() {
             intersections.add(segment);
@@ -282,7 +560,79 @@ mod day5_test {
             let parsed_segment : LineSegment = FromStr::from_str(line_segment)?;
             let mut parsed_points : Vec<Point> = points.into_iter().map(FromStr::from_str).collect::<Result<Vec<Point>, _>>()?;
 
-            assert_eq!(parsed_segment.line(true).sort(), parsed_points.sort());
+            let mut line = parsed_segment.line(LineMode::Diagonal);
+            line.sort();
+            parsed_points.sort();
+            assert_eq!(line, parsed_points);
+
+            Ok(())
+        }
+
+        #[rstest]
+        #[case("0,0 -> 4,2", vec!["0,0", "2,1", "4,2"])]
+        #[case("4,2 -> 0,0", vec!["4,2", "2,1", "0,0"])]
+        #[case("1,7 -> 4,1", vec!["1,7", "2,5", "3,3", "4,1"])]
+        #[case("0,0 -> 3,1", vec!["0,0", "3,1"])]
+        #[case("2,5 -> 2,2", vec!["2,5", "2,4", "2,3", "2,2"])]
+        #[case("3,3 -> 3,3", vec!["3,3"])]
+        fn rasterize(#[case] line_segment : &str, #[case] points : Vec<&str>) -> anyhow::Result<()> {
+            let parsed_segment : LineSegment = FromStr::from_str(line_segment)?;
+            let parsed_points : Vec<Point> = points.into_iter().map(FromStr::from_str).collect::<Result<Vec<Point>, _>>()?;
+
+            assert_eq!(parsed_segment.points().collect::<Vec<Point>>(), parsed_points);
+
+            Ok(())
+        }
+
+        #[rstest]
+        #[case("0,9 -> 5,9", 6, 6, 6)]
+        #[case("8,0 -> 0,8", 0, 9, 9)]
+        #[case("0,0 -> 4,2", 0, 0, 3)]
+        fn modes(#[case] line_segment : &str, #[case] axis : usize, #[case] diagonal : usize, #[case] all : usize) -> anyhow::Result<()> {
+            let parsed_segment : LineSegment = FromStr::from_str(line_segment)?;
+
+            assert_eq!(parsed_segment.line(LineMode::Axis).len(), axis);
+            assert_eq!(parsed_segment.line(LineMode::Diagonal).len(), diagonal);
+            assert_eq!(parsed_segment.line(LineMode::All).len(), all);
+            assert_eq!("all".parse::<LineMode>()?, LineMode::All);
+            assert!("steep".parse::<LineMode>().is_err());
+
+            Ok(())
+        }
+
+        #[rstest]
+        #[case("0,0 -> 4,4", "0,4 -> 4,0", Some(Overlap::Crossing(Point { x: 2, y: 2 })))]
+        #[case("0,0 -> 1,1", "0,1 -> 1,0", None)]
+        #[case("0,0 -> 6,3", "0,3 -> 6,0", None)]
+        #[case("0,0 -> 2,2", "3,0 -> 3,9", None)]
+        #[case("0,0 -> 2,0", "0,1 -> 2,1", None)]
+        #[case("0,0 -> 2,0", "3,0 -> 5,0", None)]
+        #[case("0,0 -> 2,0", "2,0 -> 5,0", Some(Overlap::Crossing(Point { x: 2, y: 0 })))]
+        #[case("0,0 -> 4,0", "6,0 -> 2,0", Some(Overlap::Collinear(LineSegment { from: Point { x: 2, y: 0 }, to: Point { x: 4, y: 0 } })))]
+        #[case("6,3 -> 0,0", "2,1 -> 8,4", Some(Overlap::Collinear(LineSegment { from: Point { x: 6, y: 3 }, to: Point { x: 2, y: 1 } })))]
+        #[case("1,1 -> 1,1", "0,0 -> 3,3", Some(Overlap::Crossing(Point { x: 1, y: 1 })))]
+        #[case("1,1 -> 1,1", "1,1 -> 1,1", Some(Overlap::Crossing(Point { x: 1, y: 1 })))]
+        #[case("1,1 -> 1,1", "0,0 -> 4,2", None)]
+        // cross products of these overflow an i64
+        #[case("0,0 -> 3000000000,3000000000", "0,3000000000 -> 3000000000,0", Some(Overlap::Crossing(Point { x: 1500000000, y: 1500000000 })))]
+        #[case("0,0 -> 3000000000,3000000000", "0,3000000001 -> 3000000001,0", None)]
+        #[case("0,0 -> 4000000000000,2000000000000", "0,2000000000000 -> 4000000000000,0", Some(Overlap::Crossing(Point { x: 2000000000000, y: 1000000000000 })))]
+        fn overlap(#[case] a : &str, #[case] b : &str, #[case] expected : Option<Overlap>) -> anyhow::Result<()> {
+            let a : LineSegment = a.parse()?;
+            let b : LineSegment = b.parse()?;
+
+            assert_eq!(a.overlap(&b), expected);
+            // either order finds the same points
+            let points = |overlap : Option<Overlap>| {
+                let mut points = match overlap {
+                    Some(Overlap::Crossing(point)) => vec![point],
+                    Some(Overlap::Collinear(segment)) => segment.points().collect(),
+                    None => vec![],
+                };
+                points.sort();
+                points
+            };
+            assert_eq!(points(b.overlap(&a)), points(expected));
 
             Ok(())
         }
@@ -300,7 +650,7 @@ mod day5_test {
                 },
             };
 
-            assert_eq!(line_segment.is_horizontal(), true);
+            assert!(line_segment.is_horizontal());
         }
 
         #[test]
@@ -316,7 +666,7 @@ mod day5_test {
                 },
             };
 
-            assert_eq!(line_segment.is_vertical(), true);
+            assert!(line_segment.is_vertical());
         }
         #[test]
         fn parse_line_segment() -> anyhow::Result<()> {
@@ -371,6 +721,148 @@ mod day5_test {
         Ok(())
     }
 
+    /// Segments of every slope from a seeded generator.
+    fn generated(seed : u64, segments : usize, size : usize) -> VentField {
+        let mut rng = aoc2021::Rng::new(seed);
+        let mut next = || rng.below(size);
+        VentField {
+            lines: (0..segments).map(|_| LineSegment {
+                from: Point { x: next(), y: next() },
+                to: Point { x: next(), y: next() },
+            }).collect(),
+        }
+    }
+
+    mod geometric {
+        use super::*;
+        use std::collections::HashSet;
+
+        fn rasterized(field : &VentField, mode : LineMode) -> HashSet<Point> {
+            field.intersections(mode).matrix.points.into_iter()
+                .filter(|(_, count)| *count >= 2)
+                .map(|(point, _)| point)
+                .collect()
+        }
+
+        #[test]
+        fn matches_rasterized() -> anyhow::Result<()> {
+            let fields = [load_sample(5)?, load_input(5)?, generated(1, 200, 60), generated(2, 400, 20)];
+            for field in fields.iter() {
+                for mode in [LineMode::Axis, LineMode::Diagonal, LineMode::All] {
+                    assert_eq!(field.overlapping_points(mode), rasterized(field, mode), "{:?}", mode);
+                }
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn answers() -> anyhow::Result<()> {
+            let field : VentField = load_input(5)?;
+            assert_eq!(field.overlapping_points(LineMode::Axis).len(), 6461);
+            assert_eq!(field.overlapping_points(LineMode::Diagonal).len(), 18065);
+            Ok(())
+        }
+    }
+
+    mod backends {
+        use super::*;
+
+        #[test]
+        fn dense_matches_sparse() -> anyhow::Result<()> {
+            let fields = [load_sample(5)?, load_input(5)?, generated(3, 300, 50)];
+            for field in fields.iter() {
+                for mode in [LineMode::Axis, LineMode::Diagonal, LineMode::All] {
+                    let sparse = field.count_overlaps(mode, Backend::Sparse);
+                    assert_eq!(field.count_overlaps(mode, Backend::Dense(field.bounds(mode))), sparse, "{:?}", mode);
+                    assert_eq!(field.count_overlaps(mode, field.backend(mode)), sparse, "{:?}", mode);
+                }
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn auto_select() -> anyhow::Result<()> {
+            let sample : VentField = load_sample(5)?;
+            assert_eq!(sample.backend(LineMode::Axis), Backend::Dense(Bounds { min: Point { x: 0, y: 0 }, width: 10, height: 10 }));
+
+            // the grid starts at the nearest corner, not the origin
+            let offset : VentField = InputFile {
+                data: vec![
+                    "1000,1000 -> 1005,1000".to_string(),
+                    "1002,998 -> 1002,1003".to_string(),
+                ],
+            }.try_into()?;
+            let bounds = Bounds { min: Point { x: 1000, y: 998 }, width: 6, height: 6 };
+            assert_eq!(offset.backend(LineMode::Axis), Backend::Dense(bounds));
+            assert_eq!(offset.count_overlaps(LineMode::Axis, Backend::Dense(bounds)), 1);
+
+            let far_apart : VentField = InputFile {
+                data: vec![
+                    "0,0 -> 5,0".to_string(),
+                    "900,900 -> 900,905".to_string(),
+                    "3,3 -> 1000,1997".to_string(),
+                ],
+            }.try_into()?;
+            assert_eq!(far_apart.backend(LineMode::Axis), Backend::Sparse);
+            // the sloped line isn't drawn, so it doesn't stretch the grid
+            assert_eq!(far_apart.backend(LineMode::Diagonal), Backend::Sparse);
+
+            let empty = VentField { lines: vec![] };
+            assert_eq!(empty.count_overlaps(LineMode::All, empty.backend(LineMode::All)), 0);
+            Ok(())
+        }
+
+        #[test]
+        fn saturates() {
+            let mut grid = DenseGrid::new(Bounds { min: Point { x: 0, y: 0 }, width: 2, height: 1 });
+            for _ in 0..70_000 {
+                grid.increment(1, 0);
+            }
+            assert_eq!(grid.cells, vec![0, u16::MAX]);
+            assert_eq!(grid.overlapping(), 1);
+        }
+
+        #[test]
+        #[should_panic]
+        fn outside_the_grid() {
+            let mut grid = DenseGrid::new(Bounds { min: Point { x: 5, y: 5 }, width: 2, height: 2 });
+            // one past the end of the first row would land on the second
+            grid.increment(7, 5);
+        }
+
+        /// Run with `cargo test --release --features bench --bin day5 benchmark -- --nocapture`.
+        #[cfg(feature = "bench")]
+        #[test]
+        fn benchmark() -> anyhow::Result<()> {
+            let axis_lines = |seed : u64, segments : usize| {
+                let mut field = generated(seed, segments, 10_000);
+                for segment in field.lines.iter_mut() {
+                    if segment.from.x % 2 == 0 {
+                        segment.to.y = segment.from.y;
+                    } else {
+                        segment.to.x = segment.from.x;
+                    }
+                }
+                field
+            };
+            let fields = [
+                ("input", load_input(5)?),
+                ("10k x 10k, 500 segments", generated(4, 500, 10_000)),
+                ("10k x 10k, 5k axis segments", axis_lines(5, 5_000)),
+            ];
+            for (name, field) in fields.iter() {
+                let mode = LineMode::All;
+                for backend in [Backend::Sparse, Backend::Dense(field.bounds(mode))] {
+                    let start = std::time::Instant::now();
+                    let count = field.count_overlaps(mode, backend);
+                    println!("{}: {:?} counted {} in {:?}", name, backend, count, start.elapsed());
+                }
+                println!("{}: auto selects {:?}", name, field.backend(mode));
+            }
+            Ok(())
+        }
+    }
+
     mod sparse_matrix {
         use super::super::SparseMatrix;
 
@@ -1,4 +1,5 @@
 use aoc2021::input::{InputFileError, InputFile, load_input};
+use aoc2021::Counter;
 use core::str::FromStr;
 
 fn main() -> anyhow::Result<()> {
@@ -32,7 +33,7 @@ impl Fish {
 
 #[derive(Debug, PartialEq, Default)]
 struct SchoolOfFish {
-    fish_ages : std::collections::BTreeMap<usize, i64>
+    fish_ages : Counter<usize>
 }
 
 impl std::ops::Add for SchoolOfFish {
@@ -40,20 +41,16 @@ impl std::ops::Add for SchoolOfFish {
     type Output = SchoolOfFish;
 
     fn add(self, rhs: Self) -> Self::Output {
-        let mut school = SchoolOfFish::default();
-
-        for (age, count) in rhs.fish_ages.iter() {
-            *school.fish_ages.entry(*age).or_insert(0) += *count;
+        SchoolOfFish {
+            fish_ages: self.fish_ages + rhs.fish_ages,
         }
-
-        school
     }
 }
 
 impl std::fmt::Display for SchoolOfFish {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut ages = (0_usize..=8_usize).into_iter()
-            .map(|x| format!("{}={}", x, self.fish_ages.get(&x).unwrap_or(&0)))
+        let mut ages = (0_usize..=8_usize)
+            .map(|x| format!("{}={}", x, self.fish_ages.get(&x)))
             .collect::<Vec<String>>();
         ages.reverse();
         write!(f, "{}", ages.join(","))
@@ -72,18 +69,18 @@ impl SchoolOfFish {
     }
 
     fn add(&mut self, fish : &Fish) {
-        *self.fish_ages.entry(fish.timer).or_insert(0) += 1;
+        self.fish_ages.add(fish.timer);
     }
 
     fn spawn_day(&mut self) {
-        let mut new_fish = std::collections::BTreeMap::new();
+        let mut new_fish = Counter::new();
 
         for (age, count) in self.fish_ages.iter() {
             if *age == 0 {
-                *new_fish.entry(6).or_insert(0) += *count;
-                *new_fish.entry(8).or_insert(0) += *count;
+                new_fish.add_n(6, count);
+                new_fish.add_n(8, count);
             } else {
-                *new_fish.entry(*age - 1).or_insert(0) += *count;
+                new_fish.add_n(*age - 1, count);
             }
         }
 
@@ -91,13 +88,7 @@ impl SchoolOfFish {
     }
 
     fn fish_count(&self) -> i64 {
-        let mut total = 0;
-
-        for count in self.fish_ages.values() {
-            total += count
-        }
-
-        total
+        self.fish_ages.total() as i64
     }
 
     fn spawn(&mut self, days : usize) {
@@ -44,7 +44,7 @@ impl Crabs {
     fn min(&self) -> i64 {
         let mut local = self.crabs.clone();
         local.sort();
-        if local.len() > 0 {
+        if !local.is_empty() {
             local[0].horizontal_position
         } else {
             0
@@ -54,7 +54,7 @@ impl Crabs {
         let mut local = self.crabs.clone();
         local.sort();
         local.reverse();
-        if local.len() > 0 {
+        if !local.is_empty() {
             local[0].horizontal_position
         } else {
             0
@@ -1,4 +1,5 @@
 use aoc2021::input::{InputFileError, load_input};
+use aoc2021::Counter;
 use core::str::FromStr;
 
 fn main() -> anyhow::Result<()> {
@@ -17,22 +18,16 @@ trait Day8 {
 
 impl Day8 for Vec<EncodedEntry> {
     fn part1(&self) -> i64 {
-        let mut total = 0;
-        let mut map = std::collections::HashMap::new();
-
-        for entry in self.iter() {
-            for digit in entry.outputs.iter() {
-                *map.entry(digit).or_insert(0) += 1;
-            }
-        }
+        let digits : Counter<EncodedDigit> = self.iter()
+            .flat_map(|entry| entry.outputs.iter().copied())
+            .collect();
 
-        for (digit, count) in map.into_iter() {
-            //println!("digit={} count={}", digit.value, count);
+        let mut total = 0;
+        for (digit, count) in digits.iter() {
             total += match digit.len() {
-                /* 1 */ 2 | /* 4 */ 4 | /* 7 */ 3 | /* 8 */ 7 => count,
+                /* 1 */ 2 | /* 4 */ 4 | /* 7 */ 3 | /* 8 */ 7 => count as i64,
                 _ => 0,
             };
-
         }
 
         total
@@ -96,12 +91,13 @@ impl EncodedDigit {
 }
 
 impl EncodedEntry {
+    #[cfg(test)]
     fn decode_value(&self, s : &str) -> anyhow::Result<i64> {
         let digit : EncodedDigit = FromStr::from_str(s)?;
 
         let key = self.build_key();
 
-        Ok(*key.get(&digit).unwrap_or_else(|| &0))
+        Ok(*key.get(&digit).unwrap_or(&0))
     }
 
     fn build_key(&self) -> std::collections::HashMap<EncodedDigit, i64> {
@@ -116,10 +112,10 @@ impl EncodedEntry {
             };
         }
 
-        let four = map.get_mut(&4).unwrap().clone();
-        let one = map.get_mut(&1).unwrap().clone();
-        let seven = map.get_mut(&7).unwrap().clone();
-        let eight = map.get_mut(&8).unwrap().clone();
+        let four = *map.get_mut(&4).unwrap();
+        let one = *map.get_mut(&1).unwrap();
+        let seven = *map.get_mut(&7).unwrap();
+        let eight = *map.get_mut(&8).unwrap();
 
         let a = seven - one;
         let g = self.inputs.iter()
@@ -177,7 +173,7 @@ impl EncodedEntry {
         let mut output = vec![];
         let key = self.build_key();
         for o in self.outputs.iter() {
-            match key.get(&o) {
+            match key.get(o) {
                 Some(decoded_value) =>output.push(decoded_value),
                 None => return Err(anyhow::anyhow!("unable to decode {}", o)),
             };
@@ -210,7 +206,7 @@ impl FromStr for EncodedEntry {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, PartialOrd, Ord)]
 struct EncodedDigit {
     a : bool,
     b : bool,
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+
+/// A multiset counting how often each key was added. Keys are kept sorted, so
+/// iterating is deterministic and ties in `most_common` and `least_common` are
+/// broken by the smaller key.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Counter<K : Ord> {
+    counts : BTreeMap<K, usize>,
+}
+
+impl<K : Ord> Default for Counter<K> {
+    fn default() -> Self {
+        Counter {
+            counts: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K : Ord> Counter<K> {
+    pub fn new() -> Self {
+        Counter::default()
+    }
+
+    pub fn add(&mut self, key : K) {
+        self.add_n(key, 1);
+    }
+
+    pub fn add_n(&mut self, key : K, n : usize) {
+        if n > 0 {
+            *self.counts.entry(key).or_insert(0) += n;
+        }
+    }
+
+    /// Removes up to `n` of `key`, dropping it once it reaches zero.
+    pub fn remove_n(&mut self, key : &K, n : usize) {
+        if let Some(count) = self.counts.get_mut(key) {
+            *count = count.saturating_sub(n);
+            if *count == 0 {
+                self.counts.remove(key);
+            }
+        }
+    }
+
+    pub fn get(&self, key : &K) -> usize {
+        self.counts.get(key).copied().unwrap_or(0)
+    }
+
+    /// The sum of every count.
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// The number of distinct keys.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Every key with its count, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, usize)> {
+        self.counts.iter().map(|(key, count)| (key, *count))
+    }
+
+    /// The `n` keys with the highest counts, highest first.
+    pub fn most_common(&self, n : usize) -> Vec<(&K, usize)> {
+        let mut counts : Vec<(&K, usize)> = self.iter().collect();
+        // stable, so equal counts stay in key order
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts.truncate(n);
+        counts
+    }
+
+    /// The `n` keys with the lowest counts, lowest first.
+    pub fn least_common(&self, n : usize) -> Vec<(&K, usize)> {
+        let mut counts : Vec<(&K, usize)> = self.iter().collect();
+        counts.sort_by_key(|(_, count)| *count);
+        counts.truncate(n);
+        counts
+    }
+
+    /// Adds every count of `other` to this counter.
+    pub fn merge(&mut self, other : Counter<K>) {
+        for (key, count) in other.counts {
+            self.add_n(key, count);
+        }
+    }
+
+    /// Builds a new counter by mapping every key, adding up the counts of
+    /// keys that map to the same one.
+    pub fn map_keys<J : Ord, F : FnMut(K) -> J>(self, mut f : F) -> Counter<J> {
+        let mut counter = Counter::new();
+        for (key, count) in self.counts {
+            counter.add_n(f(key), count);
+        }
+        counter
+    }
+}
+
+impl<K : Ord> std::ops::Add for Counter<K> {
+    type Output = Counter<K>;
+
+    fn add(mut self, rhs : Self) -> Self::Output {
+        self.merge(rhs);
+        self
+    }
+}
+
+impl<K : Ord> std::ops::AddAssign for Counter<K> {
+    fn add_assign(&mut self, rhs : Self) {
+        self.merge(rhs);
+    }
+}
+
+/// Subtracts counts, keeping only keys that stay above zero.
+impl<K : Ord> std::ops::Sub for Counter<K> {
+    type Output = Counter<K>;
+
+    fn sub(mut self, rhs : Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+impl<K : Ord> std::ops::SubAssign for Counter<K> {
+    fn sub_assign(&mut self, rhs : Self) {
+        for (key, count) in rhs.counts.iter() {
+            self.remove_n(key, *count);
+        }
+    }
+}
+
+/// Multiplies every count.
+impl<K : Ord> std::ops::Mul<usize> for Counter<K> {
+    type Output = Counter<K>;
+
+    fn mul(mut self, rhs : usize) -> Self::Output {
+        if rhs == 0 {
+            self.counts.clear();
+        }
+        for count in self.counts.values_mut() {
+            *count *= rhs;
+        }
+        self
+    }
+}
+
+impl<K : Ord> FromIterator<K> for Counter<K> {
+    fn from_iter<I : IntoIterator<Item = K>>(iter : I) -> Self {
+        let mut counter = Counter::new();
+        counter.extend(iter);
+        counter
+    }
+}
+
+impl<K : Ord> Extend<K> for Counter<K> {
+    fn extend<I : IntoIterator<Item = K>>(&mut self, iter : I) {
+        for key in iter {
+            self.add(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        let counter : Counter<char> = "mississippi".chars().collect();
+
+        assert_eq!(counter.get(&'s'), 4);
+        assert_eq!(counter.get(&'x'), 0);
+        assert_eq!(counter.total(), 11);
+        assert_eq!(counter.len(), 4);
+        assert_eq!(counter.iter().collect::<Vec<_>>(), vec![(&'i', 4), (&'m', 1), (&'p', 2), (&'s', 4)]);
+    }
+
+    #[test]
+    fn most_and_least_common() {
+        let counter : Counter<char> = "mississippi".chars().collect();
+
+        assert_eq!(counter.most_common(3), vec![(&'i', 4), (&'s', 4), (&'p', 2)]);
+        assert_eq!(counter.least_common(2), vec![(&'m', 1), (&'p', 2)]);
+        assert_eq!(counter.most_common(10).len(), 4);
+        assert!(Counter::<char>::new().most_common(1).is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::erasing_op)]
+    fn arithmetic() {
+        let a : Counter<char> = "aabc".chars().collect();
+        let b : Counter<char> = "abd".chars().collect();
+
+        assert_eq!(a.clone() + b.clone(), "aaabbcd".chars().collect());
+        assert_eq!(a.clone() - b.clone(), "ac".chars().collect());
+        assert_eq!(b - a.clone(), "d".chars().collect());
+        assert_eq!(a.clone() * 2, "aaaabbcc".chars().collect());
+        assert!((a.clone() * 0).is_empty());
+
+        let mut merged = a.clone();
+        merged.merge(a);
+        assert_eq!(merged.get(&'a'), 4);
+        assert_eq!(merged.map_keys(|c| c == 'a'), vec![true, true, true, true, false, false, false, false].into_iter().collect());
+    }
+}
@@ -15,6 +15,16 @@ pub enum InputFileError {
     ParseIntError(#[from] core::num::ParseIntError),
     #[error("Could not find day {0}")]
     CouldNotFindDay(String),
+    /// A typed error from a day's own parser, which callers can downcast.
+    #[error(transparent)]
+    ParseError(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Lets `load_input_text` hand back the text as is.
+impl From<std::convert::Infallible> for InputFileError {
+    fn from(infallible : std::convert::Infallible) -> Self {
+        match infallible {}
+    }
 }
 
 pub struct InputFile {
@@ -62,22 +72,86 @@ impl<O : FromStr> TryFrom<InputFile> for Vec<O> where InputFileError : From<<O a
     }
 }
 
+/// An input file split on blank lines, for puzzles whose input comes in
+/// blocks. Each section is trimmed the same way as an `InputFile`.
+pub struct InputSections {
+    pub sections : Vec<InputFile>,
+}
+
+impl InputSections {
+    pub fn new(raw_data : String) -> Self {
+        let mut sections = vec![];
+        let mut section = vec![];
+        for line in raw_data.split("\n").map(str::trim) {
+            if line.is_empty() {
+                if !section.is_empty() {
+                    sections.push(InputFile { data: std::mem::take(&mut section) });
+                }
+            } else {
+                section.push(line.to_string());
+            }
+        }
+        if !section.is_empty() {
+            sections.push(InputFile { data: section });
+        }
+        InputSections {
+            sections,
+        }
+    }
+}
+
 pub fn load_sample<O : TryFrom<InputFile>>(day : usize) -> Result<O, InputFileError> where InputFileError: From<<O as TryFrom<InputFile>>::Error> {
-    load_file(format!("day{}_sample", day))
+    Ok(InputFile::new(read_file(format!("day{}_sample", day))?).try_into()?)
 }
 
 pub fn load_input<O : TryFrom<InputFile>>(day : usize) -> Result<O, InputFileError> where InputFileError: From<<O as TryFrom<InputFile>>::Error> {
-    load_file(format!("day{}", day))
+    Ok(InputFile::new(read_file(format!("day{}", day))?).try_into()?)
+}
+
+pub fn load_sample_sections<O : TryFrom<InputSections>>(day : usize) -> Result<O, InputFileError> where InputFileError: From<<O as TryFrom<InputSections>>::Error> {
+    Ok(InputSections::new(read_file(format!("day{}_sample", day))?).try_into()?)
 }
 
-fn load_file<O : TryFrom<InputFile>>(file_name : String) -> Result<O, InputFileError> where InputFileError: From<<O as TryFrom<InputFile>>::Error> {
+pub fn load_input_sections<O : TryFrom<InputSections>>(day : usize) -> Result<O, InputFileError> where InputFileError: From<<O as TryFrom<InputSections>>::Error> {
+    Ok(InputSections::new(read_file(format!("day{}", day))?).try_into()?)
+}
+
+/// Parses the file exactly as written, blank lines and all, for inputs that
+/// need to report positions in the original file.
+pub fn load_sample_text<O : FromStr>(day : usize) -> Result<O, InputFileError> where InputFileError: From<<O as FromStr>::Err> {
+    Ok(read_file(format!("day{}_sample", day))?.parse()?)
+}
+
+pub fn load_input_text<O : FromStr>(day : usize) -> Result<O, InputFileError> where InputFileError: From<<O as FromStr>::Err> {
+    Ok(read_file(format!("day{}", day))?.parse()?)
+}
+
+/// The lines of the input, trimmed and without blank lines the same way as
+/// `InputFile`, read lazily from the embedded file so long inputs can be
+/// streamed without collecting them first.
+pub fn sample_lines(day : usize) -> Result<impl Iterator<Item = &'static str>, InputFileError> {
+    read_lines(format!("day{}_sample", day))
+}
+
+pub fn input_lines(day : usize) -> Result<impl Iterator<Item = &'static str>, InputFileError> {
+    read_lines(format!("day{}", day))
+}
+
+fn read_lines(file_name : String) -> Result<impl Iterator<Item = &'static str>, InputFileError> {
+    match INPUT_DIR.get_file(file_name.clone()).map(|file| file.contents_utf8()) {
+        Some(Some(contents)) => Ok(contents.lines().map(str::trim).filter(|line| !line.is_empty())),
+        Some(None) => Err(InputFileError::GeneralError(format!("{} is not valid UTF-8", file_name))),
+        None => Err(InputFileError::CouldNotFindDay(file_name)),
+    }
+}
+
+fn read_file(file_name : String) -> Result<String, InputFileError> {
     match INPUT_DIR.get_file(file_name.clone()) {
         Some(file) => {
             let mut buffer = String::new();
             file.contents().read_to_string(&mut buffer)?;
-            Ok(InputFile::new(buffer).try_into()?)
+            Ok(buffer)
         },
         None => Err(InputFileError::CouldNotFindDay(file_name))
     }
 }
-
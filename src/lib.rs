@@ -1,9 +1,19 @@
 pub mod input;
+mod counter;
 mod point;
+mod rng;
 
-pub use input::{InputFile, InputFileError};
+pub use input::{InputFile, InputFileError, InputSections};
 
 pub use input::load_sample;
 pub use input::load_input;
+pub use input::load_sample_sections;
+pub use input::load_input_sections;
+pub use input::load_sample_text;
+pub use input::load_input_text;
+pub use input::sample_lines;
+pub use input::input_lines;
 pub use point::Point;
+pub use counter::Counter;
+pub use rng::Rng;
 
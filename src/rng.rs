@@ -0,0 +1,55 @@
+/// A small seedable xorshift64* generator, so simulations and generated test
+/// data can be repeated.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state : u64,
+}
+
+impl Rng {
+    pub fn new(seed : u64) -> Self {
+        Rng {
+            // the state can't be zero, and nearby seeds shouldn't start out
+            // looking alike
+            state: (seed ^ 0x9e37_79b9_7f4a_7c15).max(1),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..bound`.
+    pub fn below(&mut self, bound : usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, items : &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repeatable() {
+        let mut a = Rng::new(1);
+        let mut b = Rng::new(1);
+        let sequence : Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        assert_eq!(sequence, (0..10).map(|_| b.next_u64()).collect::<Vec<u64>>());
+        assert_ne!(sequence, (0..10).map(|_| Rng::new(2).next_u64()).collect::<Vec<u64>>());
+        assert!((0..1000).all(|_| a.below(7) < 7));
+
+        let mut items : Vec<usize> = (0..50).collect();
+        a.shuffle(&mut items);
+        assert_ne!(items, (0..50).collect::<Vec<usize>>());
+        items.sort();
+        assert_eq!(items, (0..50).collect::<Vec<usize>>());
+    }
+}